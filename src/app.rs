@@ -1,91 +1,281 @@
-use std::collections::HashMap;
-use std::io;
-use std::rc::Rc;
-use std::{path::PathBuf, io::Stdout};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crossterm::event::Event;
-use crossterm::event::read;
-use crossterm::terminal::enable_raw_mode;
-use dptree::prelude::DependencyMap;
-use futures::future::select;
-use grammers_client::{Client, Config};
+use crossterm::event::{poll, read};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
-use tui::{Terminal, backend::CrosstermBackend};
+use tokio::time::{sleep_until, Instant};
+use tui::{Terminal, backend::Backend};
 
 use crate::ecs::{SystemState, step};
+use crate::notify::Alerts;
+use crate::systems::{Connection, ConnectionChanged, Notice, Reconnected};
 use crate::{ecs, tg};
 
-pub struct App{
-    client: Client,
+/// Sender id of events published by `App` itself
+pub const APP_ID: ecs::SystemId = 0;
+
+/// How often input thread checks that `App` is still running
+const INPUT_POLL: Duration = Duration::from_millis(100);
+
+pub struct App<B: Backend>{
+    client: Arc<dyn tg::TgClient>,
     inputs: mpsc::UnboundedReceiver<Event>,
-    terminal: Terminal<CrosstermBackend<Stdout>>,
-    session_path: PathBuf,
-    api_id: i32,
-    api_hash: String,
+    terminal: Terminal<B>,
+    /// Raw mode of real terminal, not used with scripted inputs
+    raw_mode: bool,
     systems: ecs::SystemList,
-    global: Rc<Mutex<DependencyMap>>,
+    layout: ecs::LayoutTree,
+    debug: ecs::Debugger,
+    global: Arc<Mutex<ecs::Resources>>,
+    bus: Arc<Mutex<ecs::EventBus>>,
+    /// Events from spawned tasks
+    remote: mpsc::UnboundedReceiver<ecs::Envelope>,
+    scheduler: Arc<Mutex<ecs::Scheduler>>,
+    /// Minimal time between redraws
+    frame: Duration,
+    last_draw: Option<Instant>,
+    /// Skipped redraw is done at this time
+    redraw_at: Option<Instant>,
+    /// Inputs received while systems were resolving
+    pending: VecDeque<Event>,
+    /// Updates from supervisor of connection, `None` before run and after it stopped
+    links: Option<mpsc::UnboundedReceiver<tg::Link>>,
+    /// Bell and title of terminal from `Alerts`, stdout of real terminal
+    alerts: Box<dyn Write + Send>,
 }
 
-impl App{
-    pub async fn new(config: Config, spath: PathBuf) -> Result<Self, tg::TgErrors> {
+impl<B: Backend> App<B>{
+    /// Input is read from stdin, client is also put to global resources as `Arc<dyn tg::TgClient>`
+    pub fn new(client: Arc<dyn tg::TgClient>, terminal: Terminal<B>) -> Self {
         let (txk, rxk) = mpsc::unbounded_channel();
-        tokio::spawn(async move {
-            while let Ok(event) = read() {
-                txk.send(event).unwrap();
+        // read() блокирующий: отдельный поток, не ждём его при выходе из runtime.
+        // poll с таймаутом, чтобы поток закончился когда run закрыл ввод
+        std::thread::spawn(move || {
+            while !txk.is_closed() {
+                match poll(INPUT_POLL) {
+                    Ok(false) => continue,
+                    Ok(true) => {}
+                    Err(_) => break,
+                }
+                match read() {
+                    Ok(event) => {
+                        if txk.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
             }
         });
+        let mut app = Self::with_inputs(client, terminal, rxk);
+        app.raw_mode = true;
+        app.alerts = Box::new(io::stdout());
+        app
+    }
 
-        let stdout = io::stdout();
-        let backend = CrosstermBackend::new(stdout);
-        let terminal = Terminal::new(backend).unwrap();
-        let api_id = config.api_id;
-        let api_hash = config.api_hash.clone();
-        let global = Rc::new(Mutex::new(DependencyMap::new()));
-        Ok(App {
-            client: Client::connect(config).await?,
-            inputs: rxk,
+    /// Scripted inputs, e.g. for `TestBackend` or remote session
+    pub fn with_inputs(client: Arc<dyn tg::TgClient>, terminal: Terminal<B>, inputs: mpsc::UnboundedReceiver<Event>) -> Self {
+        let mut deps = ecs::Resources::new();
+        deps.insert(client.clone());
+        deps.insert(Alerts::default());
+        let global = Arc::new(Mutex::new(deps));
+        let mut bus = ecs::EventBus::new();
+        let remote = bus.take_receiver().unwrap();
+        App {
+            client,
+            inputs,
+            raw_mode: false,
             systems: HashMap::new(),
+            layout: ecs::LayoutTree::single(0),
+            debug: ecs::Debugger::new(),
             terminal,
-            session_path: spath,
-            api_id,
-            api_hash,
-            global
-        })
+            global,
+            bus: Arc::new(Mutex::new(bus)),
+            remote,
+            scheduler: Arc::new(Mutex::new(ecs::Scheduler::new())),
+            frame: Duration::from_millis(33),
+            last_draw: None,
+            redraw_at: None,
+            pending: VecDeque::new(),
+            links: None,
+            alerts: Box::new(io::sink()),
+        }
     }
 
     pub fn add_system(&mut self, mut system: ecs::System<SystemState>){
         let s = system.id();
         system.global = self.global.clone();
+        system.bus = self.bus.clone();
+        system.scheduler = self.scheduler.clone();
         self.systems.insert(s, system);
     }
 
-    pub async fn run(&mut self){
-        enable_raw_mode().unwrap();
-        self.terminal.clear().unwrap();
-        let it = Box::pin(self.inputs.recv());
-        let ut = Box::pin(self.client.next_update());
-        let sel = select(it, ut);
-        let w = match sel.await{
-            futures::future::Either::Left((it, _)) => {
-                step(&mut self.terminal, &mut self.systems, it, None).await
-            },
-            futures::future::Either::Right((ut, _)) => {
-                match ut{
-                    Ok(ut)=>{
-                        step(&mut self.terminal, &mut self.systems, None, ut).await
+    /// Single system on whole screen, application ends when it reaches its end state
+    #[allow(dead_code)]
+    pub fn set_root(&mut self, id: ecs::SystemId){
+        self.layout = ecs::LayoutTree::single(id);
+    }
+
+    /// Several systems on screen, application ends when one of them reaches its end state
+    pub fn set_layout(&mut self, layout: ecs::LayoutTree){
+        self.layout = layout;
+    }
+
+    pub fn set_fps(&mut self, fps: u32){
+        self.frame = Duration::from_secs(1) / fps.max(1);
+    }
+
+    /// Redraws faster than frame rate are coalesced into one at `redraw_at`
+    fn need_draw(&mut self, now: Instant) -> bool {
+        match self.last_draw {
+            Some(l) if now < l + self.frame => {
+                self.redraw_at = Some(l + self.frame);
+                false
+            }
+            _ => {
+                self.last_draw = Some(now);
+                self.redraw_at = None;
+                true
+            }
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), ecs::EcsErrors>{
+        if self.raw_mode {
+            enable_raw_mode()?;
+        }
+        self.terminal.clear()?;
+        self.bus.lock().await.publish(APP_ID, ConnectionChanged(Connection::Online));
+        self.links = Some(tg::supervise(self.client.clone(), tg::Backoff::default()));
+        // Первый кадр без ожидания ввода
+        self.redraw_at = Some(Instant::now());
+        let r = self.run_loop().await;
+        self.links = None;
+        // Поток ввода видит закрытый канал и завершается
+        self.inputs.close();
+        if self.raw_mode {
+            disable_raw_mode()?;
+        }
+        r
+    }
+
+    async fn run_loop(&mut self) -> Result<(), ecs::EcsErrors>{
+        loop {
+            let mut link = None;
+            let (input, update) = if let Some(e) = self.pending.pop_front() {
+                (Some(e), None)
+            } else {
+                let links = &mut self.links;
+                let deadline = self.scheduler.lock().await.next_deadline();
+                let wake = match (deadline, self.redraw_at) {
+                    (Some(d), Some(r)) => Some(d.min(r)),
+                    (d, r) => d.or(r),
+                };
+                tokio::select! {
+                    // Ввод закрыт: терминал или сценарий теста закончился
+                    it = self.inputs.recv() => match it {
+                        Some(e) => (Some(e), None),
+                        None => break,
                     },
-                    Err(_)=>{
-                        println!("");
-                        false
+                    Some(e) = self.remote.recv() => {
+                        self.bus.lock().await.push(e);
+                        (None, None)
                     }
+                    _ = sleep_until(wake.unwrap_or_else(Instant::now)), if wake.is_some() => (None, None),
+                    l = async { links.as_mut()?.recv().await }, if links.is_some() => match l {
+                        Some(tg::Link::Update(u)) => (None, Some(u)),
+                        l => {
+                            link = Some(l);
+                            (None, None)
+                        }
+                    },
                 }
-                
-            },
-        };
-        // ecs::step(&mut self.terminal, &mut self.systems, input, update);
+            };
+            if let Some(l) = link {
+                self.on_link(l).await;
+            }
+            let now = Instant::now();
+            self.scheduler.lock().await.fire(now, &mut *self.bus.lock().await);
+            // Размер меняется редко, перерисовка сразу без ожидания кадра
+            let draw = self.need_draw(now) || matches!(input, Some(Event::Resize(..)));
+            // Резолверы асинхронные, ввод копим пока они работают
+            let st = step(&mut self.terminal, &mut self.systems, &self.bus, &mut self.layout, &mut self.debug, draw, input, update);
+            tokio::pin!(st);
+            let work = loop {
+                tokio::select! {
+                    w = &mut st => break w,
+                    Some(e) = self.inputs.recv() => self.pending.push_back(e),
+                }
+            };
+            // Не обработанные за шаг пробуждения не копим
+            self.scheduler.lock().await.clear_fired();
+            if !work? {
+                break;
+            }
+            if let Some(alerts) = self.global.lock().await.get_mut::<Alerts>() {
+                alerts.flush(&mut self.alerts)?;
+            }
+        }
+        Ok(())
+    }
+    /// Connection state for status bar, `None` when supervisor is stopped
+    async fn on_link(&mut self, link: Option<tg::Link>) {
+        let mut bus = self.bus.lock().await;
+        match link {
+            Some(tg::Link::Lost { error, attempt, retry_in }) => {
+                bus.publish(APP_ID, ConnectionChanged(Connection::Reconnecting(attempt)));
+                if let Some(e) = error {
+                    bus.publish(APP_ID, Notice::error(format!("Connection lost: {}, retry in {}s", e, retry_in.as_secs())));
+                }
+            }
+            Some(tg::Link::Restored { missed }) => {
+                bus.publish(APP_ID, ConnectionChanged(Connection::Online));
+                bus.publish(APP_ID, Notice::info(format!("Reconnected, {} missed updates", missed)));
+                bus.publish(APP_ID, Reconnected { missed });
+            }
+            Some(tg::Link::Update(_)) => {}
+            None => {
+                // Интерфейс остаётся рабочим, о разрыве сообщает строка состояния
+                self.links = None;
+                bus.publish(APP_ID, ConnectionChanged(Connection::Offline));
+            }
+        }
     }
-    pub fn get_global(&self)->Rc<Mutex<DependencyMap>>{
+
+    pub fn get_global(&self)->Arc<Mutex<ecs::Resources>>{
         self.global.clone()
     }
-}
\ No newline at end of file
+
+    pub fn get_bus(&self)->Arc<Mutex<ecs::EventBus>>{
+        self.bus.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use tui::backend::TestBackend;
+
+    use super::*;
+    use crate::systems::login::Login;
+
+    #[tokio::test]
+    async fn run_closes_inputs_on_exit() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let client: Arc<dyn tg::TgClient> = Arc::new(tg::FakeClient::new());
+        let terminal = Terminal::new(TestBackend::new(40, 10)).unwrap();
+        let mut app = App::with_inputs(client, terminal, rx);
+        let login = Login::system(1, app.get_global());
+        app.add_system(login);
+        app.set_root(1);
+        // Esc отменяет вход, корень закончен
+        tx.send(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), app.run()).await.unwrap().unwrap();
+        assert!(tx.is_closed());
+    }
+}
@@ -1,14 +1,67 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{ArgEnum, Parser, Subcommand};
 
 #[derive(Parser, Debug, Clone)]
 #[clap(author = "nrot", version = "0.1a")]
 pub struct Arguments {
+    /// Path to config file, default ~/.config/teleconsole/config.toml
+    #[clap(short, long)]
+    pub config: Option<PathBuf>,
     #[clap(short, long)]
     pub session_path: Option<PathBuf>,
-    #[clap(long, default_value_t = 5578726)]
-    pub api_id: i32,
-    #[clap(long, default_value = "c1449971f7d76221c6092cadc3617915")]
-    pub api_hash: String,
+    #[clap(long)]
+    pub api_id: Option<i32>,
+    #[clap(long)]
+    pub api_hash: Option<String>,
+    /// Command for new message notifications, called with sender and preview, e.g. notify-send
+    #[clap(long)]
+    pub notify_command: Option<String>,
+    /// Write log of state transitions and connection to file, off by default
+    #[clap(long)]
+    pub log: Option<PathBuf>,
+    /// Offline demo with fake Telegram server, login code is 12345
+    #[clap(long)]
+    pub demo: bool,
+    /// Terminal backend, choice depends on compiled cargo features
+    #[clap(long, arg_enum, default_value_t = Backend::default())]
+    pub backend: Backend,
+    #[clap(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    #[cfg(feature = "backend-crossterm")]
+    Crossterm,
+    #[cfg(feature = "backend-termion")]
+    Termion,
+}
+
+#[cfg(not(any(feature = "backend-crossterm", feature = "backend-termion")))]
+compile_error!("enable at least one of features backend-crossterm, backend-termion");
+
+impl Default for Backend {
+    #[cfg(feature = "backend-crossterm")]
+    fn default() -> Self {
+        Backend::Crossterm
+    }
+
+    #[cfg(not(feature = "backend-crossterm"))]
+    fn default() -> Self {
+        Backend::Termion
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Commands {
+    /// Work with configuration file
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Validate config and print effective configuration
+    Check,
 }
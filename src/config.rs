@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    args::Arguments,
+    keymap::Keymap,
+    notify::Notifier,
+    theme::{Theme, ThemeConfig},
+};
+
+pub const DEFAULT_API_ID: i32 = 5578726;
+pub const DEFAULT_API_HASH: &str = "c1449971f7d76221c6092cadc3617915";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Can`t read config {0}")]
+    Io(PathBuf, #[source] io::Error),
+    #[error("Can`t parse config {0}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("Invalid config: {0}")]
+    Invalid(String),
+    #[error("Can`t write config")]
+    Serialize(#[source] toml::ser::Error),
+}
+
+pub fn config_dir() -> PathBuf {
+    let mut h = home_dir().unwrap_or_default();
+    h.push(".config");
+    h.push("teleconsole");
+    h
+}
+
+pub fn default_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    pub bell: bool,
+    pub title: bool,
+    pub command: Option<String>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        let n = Notifier::default();
+        NotifyConfig {
+            enabled: n.enabled,
+            bell: n.bell,
+            title: n.title,
+            command: n.command,
+        }
+    }
+}
+
+impl NotifyConfig {
+    pub fn notifier(&self) -> Notifier {
+        let mut n = Notifier::new(self.command.clone());
+        n.enabled = self.enabled;
+        n.bell = self.bell;
+        n.title = self.title;
+        n
+    }
+}
+
+/// Unknown keys are errors, typo must not be ignored silently
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub api_id: i32,
+    pub api_hash: String,
+    pub session_path: PathBuf,
+    /// Name of builtin (dark, light, monochrome) or user theme from `themes`
+    pub theme: String,
+    /// Preset of key bindings: default, vim, emacs
+    pub keymap: String,
+    /// Max redraws per second
+    pub fps: u32,
+    /// Log of ECS state transitions, off when empty. Also set by `--log`
+    pub log_file: PathBuf,
+    /// Tables go after plain fields, TOML can`t write value after table
+    pub themes: HashMap<String, ThemeConfig>,
+    /// context -> key sequence -> action, over preset
+    pub keybindings: HashMap<String, HashMap<String, String>>,
+    pub notifications: NotifyConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            api_id: DEFAULT_API_ID,
+            api_hash: DEFAULT_API_HASH.to_string(),
+            session_path: config_dir().join("session"),
+            theme: String::from("dark"),
+            keymap: String::from("default"),
+            fps: 30,
+            log_file: PathBuf::new(),
+            themes: HashMap::new(),
+            keybindings: HashMap::new(),
+            notifications: NotifyConfig::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Missing file at default path is not error, defaults used
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => (default_path(), false),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Self::default()),
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))
+    }
+
+    /// Flags from command line have priority over file
+    pub fn merge_args(mut self, arg: &Arguments) -> Self {
+        if let Some(id) = arg.api_id {
+            self.api_id = id;
+        }
+        if let Some(hash) = &arg.api_hash {
+            self.api_hash = hash.clone();
+        }
+        if let Some(p) = &arg.session_path {
+            self.session_path = p.clone();
+        }
+        if let Some(c) = &arg.notify_command {
+            self.notifications.command = Some(c.clone());
+        }
+        if let Some(p) = &arg.log {
+            self.log_file = p.clone();
+        }
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.api_id <= 0 {
+            return Err(ConfigError::Invalid(format!("api_id must be positive, got {}", self.api_id)));
+        }
+        if self.api_hash.len() != 32 || !self.api_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ConfigError::Invalid(String::from("api_hash must be 32 hex characters")));
+        }
+        if self.fps == 0 {
+            return Err(ConfigError::Invalid(String::from("fps must be positive")));
+        }
+        Keymap::from_config(self)?;
+        Theme::from_config(self)?;
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> Result<String, ConfigError> {
+        toml::to_string_pretty(self).map_err(ConfigError::Serialize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::StyleConfig;
+
+    #[test]
+    fn toml_round_trip() {
+        let mut conf = AppConfig {
+            keymap: String::from("vim"),
+            theme: String::from("mine"),
+            ..Default::default()
+        };
+        conf.notifications.command = Some(String::from("notify-send"));
+        conf.keybindings.entry(String::from("dialogs")).or_default().insert(String::from("C-x a"), String::from("archive"));
+        conf.themes.insert(
+            String::from("mine"),
+            ThemeConfig {
+                base: Some(String::from("light")),
+                border: Some(StyleConfig {
+                    fg: Some(String::from("#ff8800")),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        let text = conf.to_toml().unwrap();
+        let back: AppConfig = toml::from_str(&text).unwrap();
+        back.validate().unwrap();
+        assert_eq!(back.to_toml().unwrap(), text);
+        assert_eq!(back.keybindings["dialogs"]["C-x a"], "archive");
+        assert_eq!(back.themes["mine"].border.as_ref().unwrap().fg.as_deref(), Some("#ff8800"));
+        assert_eq!(back.notifications.command.as_deref(), Some("notify-send"));
+    }
+
+    #[test]
+    fn validate_rejects_bad_values() {
+        let bad = [
+            AppConfig { fps: 0, ..Default::default() },
+            AppConfig { keymap: String::from("nano"), ..Default::default() },
+            AppConfig { theme: String::from("neon"), ..Default::default() },
+        ];
+        for conf in bad.iter() {
+            assert!(conf.validate().is_err());
+        }
+        AppConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let typos = [
+            "notifcations = 1",
+            "[notifications]\nbel = false",
+            "[themes.mine]\nbordr = { fg = \"red\" }",
+            "[themes.mine.border]\nforeground = \"red\"",
+        ];
+        for text in typos.iter() {
+            assert!(toml::from_str::<AppConfig>(text).is_err(), "{}", text);
+        }
+        let conf: AppConfig = toml::from_str("fps = 10\n[notifications]\nbell = false").unwrap();
+        assert_eq!(conf.fps, 10);
+        assert!(!conf.notifications.bell);
+    }
+}
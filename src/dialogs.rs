@@ -1,63 +1,154 @@
-use chrono::prelude::Utc;
-use grammers_client::types::Dialog;
-use tui::{widgets::{List, ListItem, StatefulWidget, Borders, Block, ListState}, style::{Style, Color}};
-use grammers_tl_types as tl;
+use tui::widgets::{List, ListItem, StatefulWidget, Borders, Block, ListState};
 
-#[derive(Debug, Clone)]
+use crate::keymap::Action;
+use crate::theme::Theme;
+use crate::text;
+use crate::tg::{self, ChatId, ChatInfo};
+
+/// Id for selection of pinned archive row, real chat can`t have it
+pub const ARCHIVE_ID: i64 = i64::MIN;
+
+#[derive(Debug, Clone, Default)]
 pub struct DialogsSelected {
     pub selected: i64,
 }
 
-#[derive(Debug, Clone)]
+const PAGE: isize = 10;
+
+impl DialogsSelected {
+    pub fn shift(&mut self, ids: &[i64], delta: isize) {
+        match ids.iter().position(|i| *i == self.selected) {
+            Some(p) => {
+                let p = (p as isize + delta).clamp(0, ids.len() as isize - 1);
+                self.selected = ids[p as usize];
+            }
+            None => self.selected = ids.first().cloned().unwrap_or(self.selected),
+        }
+    }
+
+    pub fn next(&mut self, ids: &[i64]) {
+        self.shift(ids, 1)
+    }
+
+    pub fn prev(&mut self, ids: &[i64]) {
+        self.shift(ids, -1)
+    }
+
+    /// Return false if action is not navigation
+    pub fn navigate(&mut self, ids: &[i64], action: Action) -> bool {
+        match action {
+            Action::Up => self.prev(ids),
+            Action::Down => self.next(ids),
+            Action::PageUp => self.shift(ids, -PAGE),
+            Action::PageDown => self.shift(ids, PAGE),
+            Action::Top => self.shift(ids, isize::MIN / 2),
+            Action::Bottom => self.shift(ids, isize::MAX / 2),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct OrderedDialogs {
-    header: Vec<i64>,
-    hidden: Vec<i64>,
-    all: Vec<Dialog>,
+    hidden: Vec<ChatId>,
+    /// In order of server: last message first
+    all: Vec<ChatInfo>,
+    /// Unread of archive, `None` when archive is empty
+    archive: Option<i32>,
+    theme: Theme,
 }
 
 impl OrderedDialogs {
-    pub fn new() -> Self {
-        OrderedDialogs {
-            header: Vec::new(),
-            all: Vec::new(),
-            hidden: Vec::new(),
-        }
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn insert(&mut self, d: ChatInfo) {
+        self.all.push(d);
     }
 
-    fn width(&self, d: &Dialog) -> i64 {
-        let t = Utc::now();
-        let mut w: i64 = if !self.header.contains(&d.chat.id()) {
-            u8::MAX as i64
+    /// Row of archive is shown only for not empty archive
+    pub fn set_archive(&mut self, archived: &[ChatInfo]) {
+        self.archive = if archived.is_empty() {
+            None
         } else {
-            0
+            Some(archived.iter().map(|d| d.unread).sum())
         };
-        if let Some(m) = &d.last_message {
-            w += t.timestamp() - m.date().naive_utc().timestamp();
+    }
+
+    pub fn clear(&mut self) {
+        self.all.clear();
+        self.archive = None;
+    }
+
+    pub fn get(&self, id: ChatId) -> Option<&ChatInfo> {
+        self.all.iter().find(|d| d.id == id)
+    }
+
+    pub fn remove(&mut self, id: ChatId) -> Option<ChatInfo> {
+        let i = self.all.iter().position(|d| d.id == id)?;
+        Some(self.all.remove(i))
+    }
+
+    /// Chat leaves list, its unread go to archive row
+    pub fn archive_chat(&mut self, id: ChatId) -> Option<ChatInfo> {
+        let d = self.remove(id)?;
+        self.archive = Some(self.archive.unwrap_or(0) + d.unread);
+        Some(d)
+    }
+
+    pub fn archive_unread(&self) -> Option<i32> {
+        self.archive
+    }
+
+    pub fn set_mute_until(&mut self, id: ChatId, until: i32) {
+        if let Some(d) = self.all.iter_mut().find(|d| d.id == id) {
+            d.muted = tg::mute_active(until);
         }
-        w
     }
 
-    pub fn insert(&mut self, d: Dialog) {
-        let w = self.width(&d);
-        let k = self.all.partition_point(|a| self.width(a) < w);
-        self.all.insert(k, d);
+    /// Update from stream or recovered difference, dialog with new message goes up.
+    /// Return false when chat is not in list.
+    pub fn apply(&mut self, update: &tg::TgUpdate) -> bool {
+        apply(&mut self.all, update)
     }
 
-    pub fn clear(&mut self) {
-        self.all.clear();
+    /// Unread messages of not muted chats, muted and hidden are not counted
+    pub fn unread_total(&self) -> i32 {
+        self.list().iter().filter(|d| !d.muted).map(|d| d.unread).sum()
     }
 
-    pub fn list(&self) -> Vec<Dialog> {
-        self.all
-            .iter()
-            .filter_map(|d| {
-                if !self.hidden.contains(&d.chat.id()) {
-                    Some(d.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
+    /// Ids in display order, archive row is first
+    pub fn ids(&self) -> Vec<ChatId> {
+        let mut ids = Vec::new();
+        if self.archive.is_some() {
+            ids.push(ARCHIVE_ID);
+        }
+        ids.extend(self.list().iter().map(|d| d.id));
+        ids
+    }
+
+    pub fn list(&self) -> Vec<&ChatInfo> {
+        self.all.iter().filter(|d| !self.hidden.contains(&d.id)).collect()
+    }
+}
+
+fn apply(all: &mut Vec<ChatInfo>, update: &tg::TgUpdate) -> bool {
+    match update {
+        tg::TgUpdate::NewMessage(m) => {
+            let mut d = match all.iter().position(|d| d.id == m.chat) {
+                Some(i) => all.remove(i),
+                None => return false,
+            };
+            if !m.outgoing {
+                d.unread += 1;
+            }
+            all.insert(0, d);
+            true
+        }
+        _ => true,
     }
 }
 
@@ -70,19 +161,61 @@ fn display_count(cnt: String)->String{
     }
 }
 
+#[inline]
+fn mute_icon(muted: bool)->&'static str{
+    if muted {
+        "[m]"
+    } else {
+        "   "
+    }
+}
+
+/// Name with unread counter in `width` columns, counter is never cut
 fn display_name(name: &str, width: usize, ucnt: i32)->String{
-    let cnt = if ucnt > 0 {
+    let cnt = display_count(if ucnt > 0 {
         format!("{}", ucnt)
     } else {
         String::new()
-    };
-    if name.len() > width - cnt.len(){
-        format!("{}..{}", name.chars().take(width-cnt.len()).fold(String::new(), |a, b|{
-            a + b.to_string().as_str()
-        }), display_count(cnt))
-    } else {
-        format!("{}{}", name, display_count(cnt))
+    });
+    format!("{}{}", text::truncate(name, width.saturating_sub(cnt.len())), cnt)
+}
+
+/// Row: id, text, has unread
+fn render_rows(
+    title: String,
+    rows: Vec<(i64, String, bool)>,
+    theme: &Theme,
+    area: tui::layout::Rect,
+    buf: &mut tui::buffer::Buffer,
+    state: &mut DialogsSelected,
+) {
+    let mut items = Vec::new();
+    let count = (area.height / 2) as usize;
+    let index = rows
+        .iter()
+        .position(|(id, _, _)| *id == state.selected)
+        .unwrap_or(0);
+    // Окно вокруг выбранного, индекс выбора внутри окна
+    let first = (index + 1).saturating_sub(count);
+    for (i, (_, s, unread)) in rows.into_iter().enumerate().skip(first) {
+        if i >= index + count {
+            break;
+        }
+        items.push(if i == index{
+            ListItem::new(s).style(theme.selection)
+        } else if unread {
+            ListItem::new(s).style(theme.unread)
+        } else {
+            ListItem::new(s)
+        })
     }
+    let mut slct = ListState::default();
+    // Ниже двух строк окно пустое, выбирать нечего
+    if !items.is_empty() {
+        slct.select(Some(index.saturating_sub(first)));
+    }
+    let lst = List::new(items).block(Block::default().title(title).borders(Borders::ALL).border_style(theme.border));
+    lst.render(area, buf, &mut slct);
 }
 
 impl StatefulWidget for OrderedDialogs {
@@ -93,34 +226,110 @@ impl StatefulWidget for OrderedDialogs {
         buf: &mut tui::buffer::Buffer,
         state: &mut Self::State,
     ) {
-        let mut items = Vec::new();
-        let name_size = (area.width - 1) as usize;
-        let count = (area.height / 2) as i128;
-        let dialogs = self.list();
-        let index = dialogs
+        // Внутри рамки списка
+        let name_size = area.width.saturating_sub(2) as usize;
+        let mut rows = Vec::new();
+        if let Some(unread) = self.archive_unread() {
+            rows.push((ARCHIVE_ID, if unread > 0 {
+                format!("A: Archived ({} unread)", unread)
+            } else {
+                String::from("A: Archived")
+            }, unread > 0));
+        }
+        for d in self.list() {
+            rows.push((d.id, format!("D:{}{}", mute_icon(d.muted), display_name(&d.name, name_size.saturating_sub(5), d.unread)), d.unread > 0));
+        }
+        let title = display_name("Dialogs", name_size, self.unread_total());
+        render_rows(title, rows, &self.theme, area, buf, state);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ArchivedDialogs {
+    all: Vec<ChatInfo>,
+    theme: Theme,
+}
+
+impl ArchivedDialogs {
+    pub fn new(all: Vec<ChatInfo>) -> Self {
+        ArchivedDialogs { all, theme: Theme::default() }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn get(&self, id: ChatId) -> Option<&ChatInfo> {
+        self.all.iter().find(|d| d.id == id)
+    }
+
+    pub fn remove(&mut self, id: ChatId) -> Option<ChatInfo> {
+        let i = self.all.iter().position(|d| d.id == id)?;
+        Some(self.all.remove(i))
+    }
+
+    pub fn set_mute_until(&mut self, id: ChatId, until: i32) {
+        if let Some(d) = self.all.iter_mut().find(|d| d.id == id) {
+            d.muted = tg::mute_active(until);
+        }
+    }
+
+    pub fn ids(&self) -> Vec<ChatId> {
+        self.all.iter().map(|d| d.id).collect()
+    }
+
+    pub fn list(&self) -> &[ChatInfo] {
+        &self.all
+    }
+
+    /// Like `OrderedDialogs::apply`
+    pub fn apply(&mut self, update: &tg::TgUpdate) -> bool {
+        apply(&mut self.all, update)
+    }
+}
+
+impl StatefulWidget for ArchivedDialogs {
+    type State = DialogsSelected;
+    fn render(
+        self,
+        area: tui::layout::Rect,
+        buf: &mut tui::buffer::Buffer,
+        state: &mut Self::State,
+    ) {
+        // Внутри рамки списка
+        let name_size = area.width.saturating_sub(2) as usize;
+        let rows = self
+            .all
             .iter()
-            .position(|d| d.chat.id() == state.selected)
-            .unwrap_or(0);
-        for (i, dialog) in dialogs.iter().enumerate() {
-            if (i as i128) > (index as i128) - count && (i as i128) < (index as i128) + count {
-                let s = match &dialog.dialog{
-                    tl::enums::Dialog::Dialog(d)=>{
-                        format!("D: {}", display_name(dialog.chat.name(), name_size - 3, d.unread_count)) 
-                    },
-                    tl::enums::Dialog::Folder(f)=>{
-                        format!("F: {}", display_name(dialog.chat.name(), name_size - 3, f.unread_unmuted_messages_count)) 
-                    }
-                };
-                items.push(if i == index{
-                    ListItem::new(s).style(Style::default().bg(Color::LightGreen))
-                } else {
-                    ListItem::new(s)
-                })
-            }
+            .map(|d| (d.id, format!("D:{}{}", mute_icon(d.muted), display_name(&d.name, name_size.saturating_sub(5), d.unread)), d.unread > 0))
+            .collect();
+        render_rows(String::from("Archive"), rows, &self.theme, area, buf, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tui::{buffer::Buffer, layout::Rect};
+
+    use super::*;
+
+    #[test]
+    fn low_area_is_not_panic() {
+        let mut dialogs = OrderedDialogs::default();
+        for id in 1..4 {
+            dialogs.insert(ChatInfo {
+                id,
+                name: format!("Chat {}", id),
+                unread: 0,
+                muted: false,
+            });
+        }
+        let mut state = DialogsSelected { selected: 3 };
+        for height in 0..4 {
+            let area = Rect::new(0, 0, 20, height);
+            let mut buf = Buffer::empty(area);
+            dialogs.clone().render(area, &mut buf, &mut state);
         }
-        let mut slct = ListState::default();
-        slct.select(Some(index));
-        let lst = List::new(items).block(Block::default().borders(Borders::ALL));
-        lst.render(area, buf, &mut slct);
     }
 }
@@ -0,0 +1,182 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::VecDeque,
+    pin::Pin,
+    sync::Arc,
+};
+
+use futures::Future;
+use tokio::sync::{mpsc, Mutex};
+
+use super::{EcsErrors, System, SystemId, SystemList, SystemState};
+
+/// Protection from handlers which publish events to each other forever
+pub const MAX_DELIVERIES: usize = 1024;
+
+pub type EventHandler = Arc<
+    dyn for<'a> Fn(&'a mut System<SystemState>, &'a (dyn Any + Send + Sync)) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>
+        + Send
+        + Sync,
+>;
+
+pub struct Envelope {
+    type_id: TypeId,
+    name: &'static str,
+    from: SystemId,
+    event: Arc<dyn Any + Send + Sync>,
+}
+
+impl Envelope {
+    fn new<E: Any + Send + Sync>(from: SystemId, event: E) -> Self {
+        Envelope {
+            type_id: TypeId::of::<E>(),
+            name: type_name::<E>(),
+            from,
+            event: Arc::new(event),
+        }
+    }
+}
+
+/// Publishing from spawned tasks, events are queued to bus by `App::run`
+#[derive(Clone)]
+pub struct EventSender {
+    from: SystemId,
+    tx: mpsc::UnboundedSender<Envelope>,
+}
+
+impl EventSender {
+    /// False when application is closed
+    pub fn send<E: Any + Send + Sync>(&self, event: E) -> bool {
+        self.tx.send(Envelope::new(self.from, event)).is_ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub event: &'static str,
+    pub from: SystemId,
+    pub to: SystemId,
+}
+
+/// Typed publish/subscribe between systems. Events are delivered in `ecs::step` after resolvers:
+/// in order of publishing, to subscribers in order of their ids.
+pub struct EventBus {
+    queue: VecDeque<Envelope>,
+    log: Vec<Delivery>,
+    logging: bool,
+    tx: mpsc::UnboundedSender<Envelope>,
+    rx: Option<mpsc::UnboundedReceiver<Envelope>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        EventBus {
+            queue: VecDeque::new(),
+            log: Vec::new(),
+            logging: false,
+            tx,
+            rx: Some(rx),
+        }
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    /// Keep log of deliveries for tests
+    #[cfg(test)]
+    pub fn with_log(mut self) -> Self {
+        self.logging = true;
+        self
+    }
+
+    pub fn publish<E: Any + Send + Sync>(&mut self, from: SystemId, event: E) {
+        self.queue.push_back(Envelope::new(from, event));
+    }
+
+    pub fn sender(&self, from: SystemId) -> EventSender {
+        EventSender {
+            from,
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Receiver of events from `EventSender`, taken once by `App`
+    pub fn take_receiver(&mut self) -> Option<mpsc::UnboundedReceiver<Envelope>> {
+        self.rx.take()
+    }
+
+    /// Queue event received from `EventSender`
+    pub fn push(&mut self, envelope: Envelope) {
+        self.queue.push_back(envelope);
+    }
+
+    #[cfg(test)]
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[cfg(test)]
+    pub fn deliveries(&self) -> &[Delivery] {
+        &self.log
+    }
+}
+
+fn handler<F>(f: F) -> F
+where
+    F: for<'a> Fn(&'a mut System<SystemState>, &'a (dyn Any + Send + Sync)) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>,
+{
+    f
+}
+
+impl System<SystemState> {
+    /// Subscribe system to events of type `E`
+    pub fn on_event<E: Any + Send + Sync, F>(&mut self, f: F)
+    where
+        F: for<'a> Fn(&'a mut System<SystemState>, &'a E) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> + Send + Sync + 'static,
+    {
+        let h = handler(move |system, event| match event.downcast_ref::<E>() {
+            Some(e) => f(system, e),
+            None => Box::pin(async {}),
+        });
+        self.handlers.insert(TypeId::of::<E>(), Arc::new(h));
+    }
+}
+
+pub async fn dispatch(systems: &mut SystemList, bus: &Arc<Mutex<EventBus>>) -> Result<(), EcsErrors> {
+    let mut ids: Vec<SystemId> = systems.keys().cloned().collect();
+    ids.sort_unstable();
+    let mut delivered = 0;
+    loop {
+        let envelope = bus.lock().await.queue.pop_front();
+        let envelope = match envelope {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+        for id in ids.iter() {
+            let system = systems.get_mut(id).ok_or(EcsErrors::NoSystem(*id))?;
+            let h = match system.handlers.get(&envelope.type_id) {
+                Some(h) => h.clone(),
+                None => continue,
+            };
+            delivered += 1;
+            if delivered > MAX_DELIVERIES {
+                return Err(EcsErrors::EventLoop(envelope.name));
+            }
+            {
+                let mut bus = bus.lock().await;
+                if bus.logging {
+                    bus.log.push(Delivery {
+                        event: envelope.name,
+                        from: envelope.from,
+                        to: *id,
+                    });
+                }
+            }
+            h(system, envelope.event.as_ref()).await;
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{StatefulWidget, Widget},
+};
+
+/// Part of `tui::Frame` which drawers use, not tied to backend:
+/// `step` renders into buffer of any `Terminal<B>`
+pub struct Canvas<'a> {
+    buffer: &'a mut Buffer,
+    cursor: Option<(u16, u16)>,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(buffer: &'a mut Buffer) -> Self {
+        Canvas { buffer, cursor: None }
+    }
+
+    pub fn size(&self) -> Rect {
+        self.buffer.area
+    }
+
+    pub fn render_widget<W: Widget>(&mut self, widget: W, area: Rect) {
+        widget.render(area, self.buffer);
+    }
+
+    pub fn render_stateful_widget<W: StatefulWidget>(&mut self, widget: W, area: Rect, state: &mut W::State) {
+        widget.render(area, self.buffer, state);
+    }
+
+    /// Cursor is shown at last set position, hidden when not set
+    pub fn set_cursor(&mut self, x: u16, y: u16) {
+        self.cursor = Some((x, y));
+    }
+
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        self.cursor
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use tui::{
+    layout::Rect,
+    text::Spans,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use super::{Canvas, SystemId, SystemState};
+
+/// How many transitions are kept for overlay
+pub const RECENT: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub system: SystemId,
+    pub from: SystemState,
+    pub to: SystemState,
+    /// Input, update or finished subsystem which caused transition
+    pub cause: String,
+}
+
+/// Log of state transitions and overlay with active stacks, toggled by F12
+#[derive(Debug, Default)]
+pub struct Debugger {
+    recent: VecDeque<Transition>,
+    overlay: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn record(&mut self, t: Transition) {
+        tracing::debug!(system = t.system, from = ?t.from, to = ?t.to, cause = %t.cause, "transition");
+        if self.recent.len() == RECENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(t);
+    }
+
+    #[cfg(test)]
+    pub fn recent(&self) -> impl Iterator<Item = &Transition> {
+        self.recent.iter()
+    }
+
+    pub fn visible(&self) -> bool {
+        self.overlay
+    }
+
+    pub fn toggle(&mut self) {
+        self.overlay = !self.overlay;
+    }
+
+    /// True when input toggled overlay and must not be passed to systems
+    pub fn on_input(&mut self, input: &Event) -> bool {
+        match input {
+            Event::Key(KeyEvent { code: KeyCode::F(12), .. }) => {
+                self.toggle();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// `stacks` are active stacks of panes with current states
+    pub fn lines(&self, stacks: &[Vec<(SystemId, SystemState)>]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for stack in stacks {
+            let s: Vec<String> = stack.iter().map(|(id, st)| format!("{}:{:?}", id, st)).collect();
+            lines.push(s.join(" > "));
+        }
+        lines.push(String::new());
+        for t in self.recent.iter().rev() {
+            lines.push(format!("{}: {:?} -> {:?} ({})", t.system, t.from, t.to, t.cause));
+        }
+        lines
+    }
+
+    pub fn draw(&self, f: &mut Canvas, lines: Vec<String>) {
+        let size = f.size();
+        let area = Rect {
+            x: size.x + size.width / 3,
+            y: size.y,
+            width: size.width - size.width / 3,
+            height: size.height,
+        };
+        let text: Vec<Spans> = lines.into_iter().map(Spans::from).collect();
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(text).block(Block::default().title("Debug (F12)").borders(Borders::ALL)),
+            area,
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use std::pin::Pin;
+
+use futures::Future;
+
+use super::{ArgumentDrawer, ArgumentResolver, EcsErrors, System};
+
+pub type DrawerFuture<'a> = Pin<Box<dyn Future<Output = Result<(), EcsErrors>> + Send + 'a>>;
+pub type ResolverFuture<'a, State> = Pin<Box<dyn Future<Output = Result<State, EcsErrors>> + Send + 'a>>;
+
+pub trait DrawerFn: Send + Sync {
+    fn draw<'a>(&'a self, arguments: ArgumentDrawer<'a>) -> DrawerFuture<'a>;
+}
+
+pub trait ResolverFn<State>: Send + Sync {
+    fn resolve<'a>(&'a self, system: &'a mut System<State>, arguments: ArgumentResolver) -> ResolverFuture<'a, State>;
+}
+
+/// fn pointers and closures
+impl<F> DrawerFn for F
+where
+    F: for<'a> Fn(ArgumentDrawer<'a>) -> DrawerFuture<'a> + Send + Sync,
+{
+    fn draw<'a>(&'a self, arguments: ArgumentDrawer<'a>) -> DrawerFuture<'a> {
+        self(arguments)
+    }
+}
+
+impl<State, F> ResolverFn<State> for F
+where
+    F: for<'a> Fn(&'a mut System<State>, ArgumentResolver) -> ResolverFuture<'a, State> + Send + Sync,
+{
+    fn resolve<'a>(&'a self, system: &'a mut System<State>, arguments: ArgumentResolver) -> ResolverFuture<'a, State> {
+        self(system, arguments)
+    }
+}
@@ -0,0 +1,174 @@
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use tui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use super::{Canvas, SystemId};
+
+/// Smaller terminal shows only "too small" screen, systems get no input except resize
+pub const MIN_WIDTH: u16 = 30;
+pub const MIN_HEIGHT: u16 = 8;
+
+pub fn too_small(size: Rect) -> bool {
+    size.width < MIN_WIDTH || size.height < MIN_HEIGHT
+}
+
+pub fn draw_too_small(frame: &mut Canvas) {
+    let size = frame.size();
+    let text = format!(
+        "Terminal too small: {}x{}, need at least {}x{}",
+        size.width, size.height, MIN_WIDTH, MIN_HEIGHT
+    );
+    frame.render_widget(Clear, size);
+    frame.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(if size.height > 2 { Borders::ALL } else { Borders::NONE })),
+        size,
+    );
+}
+
+/// Screen split between systems, every leaf is root of its own stack of systems
+#[derive(Debug, Clone)]
+pub enum LayoutNode {
+    System(SystemId),
+    Split {
+        direction: Direction,
+        constraints: Vec<Constraint>,
+        children: Vec<LayoutNode>,
+    },
+}
+
+impl LayoutNode {
+    pub fn split(direction: Direction, children: Vec<(Constraint, LayoutNode)>) -> Self {
+        let (constraints, children) = children.into_iter().unzip();
+        LayoutNode::Split {
+            direction,
+            constraints,
+            children,
+        }
+    }
+
+    pub fn vertical(children: Vec<(Constraint, LayoutNode)>) -> Self {
+        Self::split(Direction::Vertical, children)
+    }
+
+    fn areas(&self, area: Rect, out: &mut Vec<(SystemId, Rect)>) {
+        match self {
+            LayoutNode::System(id) => out.push((*id, area)),
+            LayoutNode::Split {
+                direction,
+                constraints,
+                children,
+            } => {
+                let rects = Layout::default()
+                    .direction(direction.clone())
+                    .constraints(constraints.clone())
+                    .split(area);
+                for (child, rect) in children.iter().zip(rects) {
+                    child.areas(rect, out);
+                }
+            }
+        }
+    }
+
+    fn panes(&self, out: &mut Vec<SystemId>) {
+        match self {
+            LayoutNode::System(id) => out.push(*id),
+            LayoutNode::Split { children, .. } => children.iter().for_each(|c| c.panes(out)),
+        }
+    }
+}
+
+/// Layout with focused pane, input goes only to focused one.
+/// Tab and Shift+Tab cycle focus when there are several panes.
+#[derive(Debug, Clone)]
+pub struct LayoutTree {
+    root: LayoutNode,
+    focus: usize,
+    size: Option<Rect>,
+    /// Shown but never focused, like status bar
+    passive: Vec<SystemId>,
+}
+
+impl LayoutTree {
+    pub fn new(root: LayoutNode) -> Self {
+        LayoutTree {
+            root,
+            focus: 0,
+            size: None,
+            passive: Vec::new(),
+        }
+    }
+
+    pub fn single(id: SystemId) -> Self {
+        Self::new(LayoutNode::System(id))
+    }
+
+    pub fn with_passive(mut self, id: SystemId) -> Self {
+        self.passive.push(id);
+        self
+    }
+
+    /// Size of last frame, below minimal one systems are not drawn
+    pub fn resize(&mut self, size: Rect) {
+        self.size = Some(size);
+    }
+
+    pub fn size(&self) -> Option<Rect> {
+        self.size
+    }
+
+    /// Rects of panes in order of tree
+    pub fn areas(&self, area: Rect) -> Vec<(SystemId, Rect)> {
+        let mut out = Vec::new();
+        self.root.areas(area, &mut out);
+        out
+    }
+
+    pub fn panes(&self) -> Vec<SystemId> {
+        let mut out = Vec::new();
+        self.root.panes(&mut out);
+        out
+    }
+
+    /// Panes which can get input, focus is index in them
+    pub fn focusable(&self) -> Vec<SystemId> {
+        let mut out = self.panes();
+        out.retain(|p| !self.passive.contains(p));
+        out
+    }
+
+    pub fn focused(&self) -> Option<SystemId> {
+        self.focusable().get(self.focus).cloned()
+    }
+
+    pub fn focus_next(&mut self) {
+        let n = self.focusable().len();
+        if n > 0 {
+            self.focus = (self.focus + 1) % n;
+        }
+    }
+
+    pub fn focus_prev(&mut self) {
+        let n = self.focusable().len();
+        if n > 0 {
+            self.focus = (self.focus + n - 1) % n;
+        }
+    }
+
+    /// True when input switched focus and must not be passed to systems
+    pub fn on_input(&mut self, input: &Event) -> bool {
+        if self.focusable().len() < 2 {
+            return false;
+        }
+        match input {
+            Event::Key(KeyEvent { code: KeyCode::Tab, .. }) => self.focus_next(),
+            Event::Key(KeyEvent { code: KeyCode::BackTab, .. }) => self.focus_prev(),
+            _ => return false,
+        }
+        true
+    }
+}
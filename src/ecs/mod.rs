@@ -1,38 +1,107 @@
+
+pub mod bus;
+pub mod canvas;
+pub mod debug;
+pub mod handler;
+pub mod layout;
+pub mod resources;
 pub mod state;
+#[cfg(test)]
+pub mod testing;
+#[cfg(test)]
+mod tests;
+pub mod timer;
 
 use std::{
-    borrow::{Borrow, BorrowMut},
+    any::{Any, TypeId},
     collections::{HashMap},
-    io::Stdout,
+    io,
     pin::Pin,
-    rc::Rc,
-    sync::Arc, hash::Hash,
+    sync::Arc,
+    hash::Hash,
+    fmt::Debug,
 };
 
 use async_trait::async_trait;
 use crossterm::event::Event;
-use dptree::di::{DependencyMap, DependencySupplier};
 use futures::Future;
-use grammers_client::types::Update;
+use thiserror::Error;
 use tokio::sync::Mutex;
-use tui::{backend::CrosstermBackend, Frame, Terminal};
+use tui::{backend::Backend, buffer::Buffer, layout::Rect, widgets::Widget, Terminal};
+
+use crate::tg::TgUpdate;
 
+pub use self::bus::{Envelope, EventBus, EventHandler};
+pub use self::canvas::Canvas;
+pub use self::debug::{Debugger, Transition};
+pub use self::handler::{DrawerFn, ResolverFn};
+pub use self::layout::{LayoutNode, LayoutTree};
+pub use self::resources::Resources;
 pub use self::state::Key;
+pub use self::timer::{Scheduler, TimerId};
+pub use teleconsole_derive::{System, SystemState};
 pub struct ArgumentDrawer<'a> {
-    pub frame: Rc<Mutex<Frame<'a, CrosstermBackend<Stdout>>>>,
-    pub global: Rc<Mutex<DependencyMap>>,
-    pub local: Rc<Mutex<DependencyMap>>,
-    pub inputs: Option<Event>,
-    pub events: Rc<Mutex<Option<Update>>>,
+    pub frame: Arc<Mutex<Canvas<'a>>>,
+    /// Rect of pane, draw only inside it
+    pub area: Rect,
+    pub global: Arc<Mutex<Resources>>,
+    pub local: Arc<Mutex<Resources>>,
+}
+
+pub type DrawerPtr = for<'a> fn(arguments: ArgumentDrawer<'a>) -> Pin<Box<dyn Future<Output = Result<(), EcsErrors>> + Send + 'a>>;
+//TIPS: Продумать как передовать всю необходимую информацию во внутрь систем по цепочке
+//TIPS: Система должна иметь доступ к глобальным объектам.
+pub type Drawer = Arc<dyn DrawerFn>;
+
+pub type InputEvent = Event;
+pub type UpdateCell = Arc<Mutex<Option<TgUpdate>>>;
+
+/// Subsystem which reached its end state, passed to resolver of parent
+pub struct Finished {
+    pub id: SystemId,
+    pub state: SystemState,
+}
+
+pub struct ArgumentResolver {
+    pub id: SystemId,
+    /// Not locked while resolver works, take copies with `resource`
+    pub global: Arc<Mutex<Resources>>,
+    pub bus: Arc<Mutex<EventBus>>,
+    pub scheduler: Arc<Mutex<Scheduler>>,
+    /// Timers of this system fired before step
+    pub timers: Vec<TimerId>,
+    pub local: Arc<Mutex<Resources>>,
+    /// Input is given only to deepest system, parents get `None`
+    pub input: Option<InputEvent>,
+    pub update: UpdateCell,
+    pub finished: Option<Finished>,
 }
 
-pub type Drawer = fn(arguments: ArgumentDrawer) -> Pin<Box<dyn Future<Output = ()>>>; //TODO: Переделать в динамические аргументы ?????
-                                                                                     //TIPS: Продумать как передовать всю необходимую информацию во внутрь систем по цепочке
-                                                                                     //TIPS: Система должна иметь доступ к глобальным объектам.
+impl ArgumentResolver {
+    /// Copy of global resource, lock is released before return
+    pub async fn resource<T: Clone + Send + Sync + 'static>(&self) -> Result<T, EcsErrors> {
+        self.global.lock().await.require::<T>().cloned()
+    }
+
+    /// Run network work out of step, its result comes to bus as event from this system
+    pub async fn spawn<F>(&self, task: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: Any + Send + Sync,
+    {
+        let tx = self.bus.lock().await.sender(self.id);
+        tokio::spawn(async move {
+            tx.send(task.await);
+        });
+    }
+}
 
 //TIPS: Подсистема работает только во время своей функции run
-pub type Resolver<State> =
-    fn(system: &mut System<State>, input: Option<Event>, update: Rc<Mutex<Option<Update>>>) -> State;
+pub type ResolverPtr<State> = for<'a> fn(
+    system: &'a mut System<State>,
+    arguments: ArgumentResolver,
+) -> Pin<Box<dyn Future<Output = Result<State, EcsErrors>> + Send + 'a>>;
+pub type Resolver<State> = Arc<dyn ResolverFn<State>>;
 
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -41,6 +110,22 @@ pub enum RunState {
     Ready,
 }
 
+#[derive(Debug, Error)]
+pub enum EcsErrors {
+    #[error("System {0} not found")]
+    NoSystem(SystemId),
+    #[error("System {0} has no resolver for current state")]
+    NoResolver(SystemId),
+    #[error("Too many deliveries of events, last was {0}")]
+    EventLoop(&'static str),
+    #[error("Resource {0} not found")]
+    NoResource(&'static str),
+    #[error("Subsystems make cycle: {0:?}")]
+    SubsystemCycle(Vec<SystemId>),
+    #[error("Terminal error")]
+    Terminal(#[from] io::Error),
+}
+
 
 pub type SystemId = usize;
 pub type SystemState = Box<dyn Key>;
@@ -49,12 +134,16 @@ pub type SystemList = HashMap<SystemId, System<SystemState>>;
 pub struct System<State> {
     id: SystemId,
     pub state: State,
+    pub istate: State,
     pub estate: State,
     pub drawer: HashMap<State, Vec<Drawer>>,
-    pub global: Rc<Mutex<DependencyMap>>,
-    pub local: Rc<Mutex<DependencyMap>>,
+    pub global: Arc<Mutex<Resources>>,
+    pub local: Arc<Mutex<Resources>>,
     pub sub_system: HashMap<State, SystemId>,
     pub resolver: HashMap<State, Resolver<State>>,
+    pub bus: Arc<Mutex<EventBus>>,
+    pub scheduler: Arc<Mutex<Scheduler>>,
+    pub handlers: HashMap<TypeId, EventHandler>,
 }
 
 impl<State> System<State>{
@@ -63,6 +152,13 @@ impl<State> System<State>{
     }
 }
 
+impl<State: Clone> System<State>{
+    /// Back to initial state, done when system is popped from stack
+    pub fn reset(&mut self){
+        self.state = self.istate.clone();
+    }
+}
+
 impl<State> Hash for System<State> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         Hash::hash(&self.id, state);
@@ -70,32 +166,53 @@ impl<State> Hash for System<State> {
 }
 
 impl System<SystemState> {
-    pub fn new<State: 'static + Key>(id: SystemId, istate: State, estate: State, global: Rc<Mutex<DependencyMap>>) -> Self {
+    pub fn new<State: 'static + Key>(id: SystemId, istate: State, estate: State, global: Arc<Mutex<Resources>>) -> Self {
+        let istate: SystemState = Box::new(istate);
         System {
             id,
-            state: Box::new(istate),
+            state: istate.clone(),
+            istate,
             estate: Box::new(estate),
             drawer: HashMap::new(),
             global,
-            local: Rc::new(Mutex::new(DependencyMap::new())),
+            local: Arc::new(Mutex::new(Resources::new())),
             sub_system: HashMap::new(),
             resolver: HashMap::new(),
+            bus: Arc::new(Mutex::new(EventBus::new())),
+            scheduler: Arc::new(Mutex::new(Scheduler::new())),
+            handlers: HashMap::new(),
         }
     }
 }
 
-pub trait ExecSystem<State: Hash + Eq>:
-    ExecSystemLocals<State> + ExecSystemDeps<State>
-{
+/// Struct as system, its fields are locals. Implemented by `#[derive(System)]`
+pub trait SystemStruct: Send + Sync + Sized + 'static {
+    type State: Eq + Hash + Clone + Send + Sync + Debug;
+    fn init_state() -> Self::State;
+    fn end_state() -> Self::State;
+    fn register(system: &mut System<SystemState>);
+}
+
+impl System<SystemState> {
+    pub fn from_struct<S: SystemStruct>(id: SystemId, value: S, global: Arc<Mutex<Resources>>) -> Self {
+        let mut local = Resources::new();
+        local.insert(Arc::new(Mutex::new(value)));
+        let mut system = System::new(id, S::init_state(), S::end_state(), global);
+        system.local = Arc::new(Mutex::new(local));
+        S::register(&mut system);
+        system
+    }
 }
-impl<T: ExecSystemLocals<State> + ExecSystemDeps<State>, State: Hash + Eq>
-    ExecSystem<State> for T
-{
+
+pub async fn system_struct<S: SystemStruct>(local: &Arc<Mutex<Resources>>) -> Result<Arc<Mutex<S>>, EcsErrors> {
+    local.lock().await.require::<Arc<Mutex<S>>>().cloned()
 }
 
 pub trait ExecSystemLocals<State: Hash> {
-    fn add_drawer(&mut self, state: State, drawer: Drawer);
-    fn set_resolver(&mut self, state: State, resolver: Resolver<State>);
+    fn add_drawer(&mut self, state: State, drawer: impl DrawerFn + 'static);
+    fn set_resolver(&mut self, state: State, resolver: impl ResolverFn<State> + 'static);
+    fn add_drawer_rc(&mut self, state: State, drawer: Drawer);
+    fn set_resolver_rc(&mut self, state: State, resolver: Resolver<State>);
     fn set_subsystem(&mut self, state: State, system: SystemId);
     fn get_subsystem_of_state(&self) -> Option<SystemId>;
     fn get_drawer_of_state(&self)->Vec<Drawer>;
@@ -103,14 +220,20 @@ pub trait ExecSystemLocals<State: Hash> {
 }
 //TODO: Реализовать рекурсивную структуру ECS с возможностью подтипов
 
-impl<State: Hash + Eq> ExecSystemLocals<State> for System<State> {
-    fn add_drawer(&mut self, state: State, drawer: Drawer) {
-        if let Some(v) = self.drawer.get_mut(&state) {
-            v.push(drawer);
-        }
+impl<State: Hash + Eq + Send + Sync + 'static> ExecSystemLocals<State> for System<State> {
+    fn add_drawer(&mut self, state: State, drawer: impl DrawerFn + 'static) {
+        self.add_drawer_rc(state, Arc::new(drawer));
+    }
+
+    fn set_resolver(&mut self, state: State, resolver: impl ResolverFn<State> + 'static) {
+        self.set_resolver_rc(state, Arc::new(resolver));
+    }
+
+    fn add_drawer_rc(&mut self, state: State, drawer: Drawer) {
+        self.drawer.entry(state).or_default().push(drawer);
     }
 
-    fn set_resolver(&mut self, state: State, resolver: Resolver<State>) {
+    fn set_resolver_rc(&mut self, state: State, resolver: Resolver<State>) {
         self.resolver.insert(state, resolver);
     }
 
@@ -122,10 +245,7 @@ impl<State: Hash + Eq> ExecSystemLocals<State> for System<State> {
     }
 
     fn get_drawer_of_state(&self)->Vec<Drawer>{
-        match self.drawer.get(&self.state).cloned(){
-            Some(l) => l,
-            None => Vec::new(),
-        }
+        self.drawer.get(&self.state).cloned().unwrap_or_default()
     }
 
     fn get_resolver_of_state(&self)->Option<Resolver<State>> {
@@ -134,105 +254,229 @@ impl<State: Hash + Eq> ExecSystemLocals<State> for System<State> {
     
 }
 
-#[async_trait(?Send)]
+#[async_trait]
 pub trait ExecSystemDeps<State> {
-    async fn add_local<T: Send + Sync + 'static>(&mut self, value: T);
-    async fn get_local<V: Send + Sync + 'static>(&mut self) -> Arc<V>;
-    fn run(&mut self, input: Option<Event>, events: Rc<Mutex<Option<Update>>>) -> RunState;
+    async fn run(&mut self, input: Option<Event>, events: Arc<Mutex<Option<TgUpdate>>>, finished: Option<Finished>) -> Result<RunState, EcsErrors>;
 }
 
-#[async_trait(?Send)]
-impl<State: Hash + Eq> ExecSystemDeps<State> for System<State> {
-    async fn add_local<T: Send + Sync + 'static>(&mut self, value: T) {
-        self.local.lock().await.borrow_mut().insert(value);
+#[async_trait]
+impl<State: Hash + Eq + Send + Sync + 'static> ExecSystemDeps<State> for System<State> {
+    async fn run(&mut self, input: Option<Event>, events: Arc<Mutex<Option<TgUpdate>>>, finished: Option<Finished>) -> Result<RunState, EcsErrors> {
+        let resolver = self.get_resolver_of_state().ok_or(EcsErrors::NoResolver(self.id))?;
+        let global = self.global.clone();
+        let local = self.local.clone();
+        let id = self.id;
+        let bus = self.bus.clone();
+        let scheduler = self.scheduler.clone();
+        let timers = scheduler.lock().await.take_fired(id);
+        let nstate = resolver.resolve(self, ArgumentResolver {
+            id,
+            global,
+            bus,
+            scheduler,
+            timers,
+            local,
+            input,
+            update: events,
+            finished,
+        })
+        .await?;
+        let ready = self.estate == nstate;
+        self.state = nstate;
+        if ready {
+            Ok(RunState::Ready)
+        } else {
+            Ok(RunState::Tick)
+        }
     }
+}
 
-    async fn get_local<V: Send + Sync + 'static>(&mut self) -> Arc<V> {
-        self.local.lock().await.borrow().get()
+/// Kind of input for transitions log
+fn input_kind(input: &Event) -> &'static str {
+    match input {
+        Event::Key(_) => "key",
+        Event::Mouse(_) => "mouse",
+        Event::Resize(..) => "resize",
     }
+}
 
-    fn run(&mut self, input: Option<Event>, events: Rc<Mutex<Option<Update>>>) -> RunState {
-        let resolver = self.get_resolver_of_state().expect("Not have resolver for system");
-        let nstate = resolver(self, input, events);
-        if self.estate == nstate{
-            self.state = nstate;
-            RunState::Ready
-        } else {
-            RunState::Tick
+/// Active stack from root to deepest system by `sub_system` of current states.
+/// System which is already in stack is error, cycle has no deepest system.
+pub fn active_stack(systems: &SystemList, root: SystemId) -> Result<Vec<SystemId>, EcsErrors> {
+    let mut stack = vec![root];
+    loop {
+        let id = *stack.last().unwrap();
+        let system = systems.get(&id).ok_or(EcsErrors::NoSystem(id))?;
+        match system.get_subsystem_of_state() {
+            Some(s) if stack.contains(&s) => {
+                stack.push(s);
+                return Err(EcsErrors::SubsystemCycle(stack));
+            }
+            Some(s) => stack.push(s),
+            None => break,
         }
     }
+    Ok(stack)
 }
 
-pub async fn step(
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+/// Resolve deepest system of stack. When system reaches its end state
+/// it is popped: reset to initial state and parent resolver gets `Finished`.
+/// Return false when root of stack is finished.
+async fn resolve_stack(
     systems: &mut SystemList,
+    debug: &mut Debugger,
+    root: SystemId,
     input: Option<Event>,
-    update: Option<Update>,
-) -> bool {
-    let mut stack = vec![0];
-
-    while let Some(system) = systems.get(stack.last().unwrap()) {
-        match system.get_subsystem_of_state() {
-            Some(s) => stack.push(s),
-            None => break,
+    events: UpdateCell,
+) -> Result<bool, EcsErrors> {
+    let mut stack = active_stack(systems, root)?;
+    let mut id = stack.pop().unwrap();
+    let mut input = input;
+    let mut finished: Option<Finished> = None;
+    loop {
+        let system = systems.get_mut(&id).ok_or(EcsErrors::NoSystem(id))?;
+        let from = system.state.clone();
+        // Только вид события: в клавишах и сообщениях личные данные
+        let cause = match (&input, &finished) {
+            (Some(i), _) => input_kind(i).to_string(),
+            (None, Some(f)) => format!("finished {}:{:?}", f.id, f.state),
+            (None, None) => String::new(),
+        };
+        let run = system.run(input.take(), events.clone(), finished.take()).await?;
+        if !Key::eq(system.state.as_ref(), from.as_ref()) {
+            let cause = match events.lock().await.as_ref() {
+                Some(u) if cause.is_empty() => u.kind().to_string(),
+                _ => cause,
+            };
+            debug.record(Transition {
+                system: id,
+                from,
+                to: system.state.clone(),
+                cause,
+            });
+        }
+        if RunState::Tick == run {
+            return Ok(true);
+        }
+        let state = system.state.clone();
+        system.reset();
+        match stack.pop() {
+            Some(parent) => {
+                finished = Some(Finished { id, state });
+                id = parent;
+            }
+            None => return Ok(false),
         }
     }
+}
 
-    let mut system = systems.get_mut(&stack.pop().unwrap()).unwrap();
-    let frame = Rc::new(Mutex::new(terminal.get_frame()));
-    let events = Rc::new(Mutex::new(update));
-    
-    for drawer in system.get_drawer_of_state().iter(){
-        drawer(ArgumentDrawer{
-            events: events.clone(),
-            frame: frame.clone(),
-            inputs: input,
-            global: system.global.clone(),
-            local: system.local.clone()
-        }).await;
-    }
-
-    while RunState::Ready == system.run(input, events.clone()){
-        if let Some(s) = stack.pop(){
-            system = systems.get_mut(&s).unwrap();
-        } else {
-            break;
+/// Buffer drawn by async drawers, copied into frame of `Terminal::draw`
+struct Drawn(Buffer);
+
+impl Widget for Drawn {
+    fn render(self, _area: Rect, buf: &mut Buffer) {
+        buf.merge(&self.0);
+    }
+}
+
+/// `Terminal::draw` flushes only difference with previous frame, sets cursor and swaps buffers
+fn present<B: Backend>(terminal: &mut Terminal<B>, buffer: Buffer, cursor: Option<(u16, u16)>) -> io::Result<()> {
+    terminal.draw(|f| {
+        let area = f.size();
+        f.render_widget(Drawn(buffer), area);
+        if let Some((x, y)) = cursor {
+            f.set_cursor(x, y);
         }
+    })?;
+    Ok(())
+}
+
+/// Resolve panes: input only to focused pane, update and resize to all of them.
+/// Events published by resolvers are dispatched after, then deepest system of every pane
+/// is drawn into its rect in one frame, so screen shows state after this step.
+/// Below `layout::MIN_WIDTH`x`MIN_HEIGHT` only "too small" screen is drawn.
+/// Drawers are skipped when `draw` is false, so `App` limits frame rate.
+/// Return false when root of any pane is finished.
+#[allow(clippy::too_many_arguments)]
+pub async fn step<B: Backend>(
+    terminal: &mut Terminal<B>,
+    systems: &mut SystemList,
+    bus: &Arc<Mutex<EventBus>>,
+    layout: &mut LayoutTree,
+    debug: &mut Debugger,
+    draw: bool,
+    input: Option<Event>,
+    update: Option<TgUpdate>,
+) -> Result<bool, EcsErrors> {
+    let resized = matches!(input, Some(Event::Resize(..)));
+    if draw || resized {
+        terminal.autoresize()?;
+        layout.resize(terminal.size()?);
     }
-    !stack.is_empty()
-    
+    let small = layout.size().is_some_and(layout::too_small);
+    let input = match input {
+        Some(e) if layout.on_input(&e) || debug.on_input(&e) => None,
+        // Пользователь не видит систем, ввод вслепую не нужен
+        Some(_) if small && !resized => None,
+        i => i,
+    };
+    let focused = layout.focused();
+    let events = Arc::new(Mutex::new(update));
 
-    // if let Some(ss) = slf.sub_system.get(&slf.state) {
-    //     if let Some(ss) = systems.get_mut(ss) {
-    //         match *run(ss, systems, input, update).await {
-    //             RunState::Ready(Ok(_)) => {}
-    //             RunState::Tick => {}
-    //             RunState::Ready(Err(e)) => {}
-    //         }
-    //     }
-    // }
-    // let global = slf.global.lock().await;
-    // let mut rxk: Arc<mpsc::UnboundedReceiver<Event>> = global.get();
-    // let mut mc: Arc<Mutex<Client>> = global.get();
-    // let mut client = mc.lock().await.borrow_mut();
-    // nu = Box::pin(client.next_update());
-    // select(Box::pin(rxk.recv()), nu); //Как получать и ввод и обновления с минимальными задержками
-
-    // if let Some(vd) = slf.drawer.get(&slf.state) {
-    //     let mut mt: Arc<Mutex<Terminal<CrosstermBackend<Stdout>>>> = global.get();
-    //     let mut t = mt.lock().await.borrow_mut();
-    //     let mut frame = t.get_frame();
-    //     for d in vd.iter() {
-    //         d(ArgumenDrawer {
-    //             global: slf.global.clone(),
-    //             local: slf.local.clone(),
-    //             frame: &mut frame,
-    //             inputs: input,
-    //             events: update,
-    //         });
-    //     }
-    // }
-    // if let Some(r) = slf.resolver.get(&slf.state) {
-    //     slf.state = r();
-    // };
+    let mut work = true;
+    for pane in layout.panes() {
+        // Resize получают все панели
+        let input = if resized || Some(pane) == focused { input } else { None };
+        work &= resolve_stack(systems, debug, pane, input, events.clone()).await?;
+    }
+    bus::dispatch(systems, bus).await?;
+    if !work || !draw {
+        return Ok(work);
+    }
+
+    if small {
+        let mut buffer = Buffer::empty(terminal.size()?);
+        layout::draw_too_small(&mut Canvas::new(&mut buffer));
+        present(terminal, buffer, None)?;
+        return Ok(work);
+    }
+    let size = terminal.size()?;
+    let mut buffer = Buffer::empty(size);
+    // Список до frame: drawer заимствуется на время жизни frame
+    let mut panes = Vec::new();
+    for (pane, area) in layout.areas(size) {
+        let id = *active_stack(systems, pane)?.last().unwrap();
+        let system = systems.get(&id).ok_or(EcsErrors::NoSystem(id))?;
+        panes.push((area, system.get_drawer_of_state(), system.global.clone(), system.local.clone()));
+    }
+    let overlay = if debug.visible() {
+        let mut stacks = Vec::new();
+        for pane in layout.panes() {
+            let stack = active_stack(systems, pane)?;
+            stacks.push(stack.into_iter().map(|id| (id, systems[&id].state.clone())).collect::<Vec<_>>());
+        }
+        Some(debug.lines(&stacks))
+    } else {
+        None
+    };
+    let cursor = {
+        let frame = Arc::new(Mutex::new(Canvas::new(&mut buffer)));
+        for (area, drawers, global, local) in panes.iter() {
+            for drawer in drawers.iter() {
+                drawer.draw(ArgumentDrawer {
+                    frame: frame.clone(),
+                    area: *area,
+                    global: global.clone(),
+                    local: local.clone(),
+                }).await?;
+            }
+        }
+        if let Some(lines) = overlay {
+            debug.draw(&mut *frame.lock().await, lines);
+        }
+        let cursor = frame.lock().await.cursor();
+        cursor
+    };
+    present(terminal, buffer, cursor)?;
+    Ok(work)
 }
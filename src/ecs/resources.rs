@@ -0,0 +1,40 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+};
+
+use super::EcsErrors;
+
+/// Typed store of resources: one value per type.
+/// `App` keeps global one, every system has its own local one.
+#[derive(Default)]
+pub struct Resources {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Resources::default()
+    }
+
+    /// Replace value of same type, old one is returned
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|v| v.downcast_ref())
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map.get_mut(&TypeId::of::<T>()).and_then(|v| v.downcast_mut())
+    }
+
+    /// `get` with error naming missing type
+    pub fn require<T: Send + Sync + 'static>(&self) -> Result<&T, EcsErrors> {
+        self.get().ok_or(EcsErrors::NoResource(type_name::<T>()))
+    }
+}
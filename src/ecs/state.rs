@@ -9,18 +9,22 @@
 Сделать возможность любой структуры быть системой и отказаться
 от использования локальных переменных в сторону полей структуры.
 Для этого нужен трейт System и процедурный макрос который реализует этот трейт
+
+Сделано: teleconsole-derive, #[derive(SystemState)] и #[derive(System)] (трейт SystemStruct)
 */
 
-use std::{any::{Any, TypeId}, hash::{Hash, Hasher}, collections::hash_map::DefaultHasher};
+use std::{any::{Any, TypeId}, hash::{Hash, Hasher}, collections::hash_map::DefaultHasher, fmt};
 
 
-pub trait Key {
+pub trait Key: Send + Sync {
     fn eq(&self, other: &dyn Key) -> bool;
     fn hash(&self) -> u64;
     fn as_any(&self) -> &dyn Any;
+    fn clone_key(&self) -> Box<dyn Key>;
+    fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
 }
 
-impl<T: Eq + Hash + 'static> Key for T {
+impl<T: Eq + Hash + Clone + Send + Sync + fmt::Debug + 'static> Key for T {
     fn eq(&self, other: &dyn Key) -> bool {
         if let Some(other) = other.as_any().downcast_ref::<T>() {
             return self == other;
@@ -39,6 +43,27 @@ impl<T: Eq + Hash + 'static> Key for T {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn clone_key(&self) -> Box<dyn Key> {
+        Box::new(self.clone())
+    }
+
+    fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// States in logs and debug overlay
+impl fmt::Debug for dyn Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_key(f)
+    }
+}
+
+impl Clone for Box<dyn Key> {
+    fn clone(&self) -> Self {
+        self.as_ref().clone_key()
+    }
 }
 
 impl PartialEq for Box<dyn Key> {
@@ -55,8 +80,3 @@ impl Hash for Box<dyn Key> {
         state.write_u64(key_hash);
     }
 }
-
-///Depreceted
-pub(crate) fn into_key(key: impl Eq + Hash + 'static) -> Box<dyn Key> {
-    Box::new(key)
-}
\ No newline at end of file
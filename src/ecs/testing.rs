@@ -0,0 +1,128 @@
+//! Headless run of `step` for tests: `TestBackend` instead of terminal, scripted inputs and updates
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+use tui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+use crate::tg::TgUpdate;
+
+use super::{step, Debugger, EcsErrors, Envelope, EventBus, Key, LayoutTree, Resources, Scheduler, System, SystemId, SystemList, SystemState};
+
+pub struct Harness {
+    pub terminal: Terminal<TestBackend>,
+    pub systems: SystemList,
+    pub global: Arc<Mutex<Resources>>,
+    pub bus: Arc<Mutex<EventBus>>,
+    pub scheduler: Arc<Mutex<Scheduler>>,
+    pub layout: LayoutTree,
+    pub debug: Debugger,
+    /// Events of spawned tasks, `remote` gives them to bus like `App::run`
+    remote: mpsc::UnboundedReceiver<Envelope>,
+}
+
+impl Harness {
+    pub fn new(width: u16, height: u16) -> Self {
+        let mut bus = EventBus::new().with_log();
+        let remote = bus.take_receiver().unwrap();
+        Harness {
+            terminal: Terminal::new(TestBackend::new(width, height)).unwrap(),
+            systems: HashMap::new(),
+            global: Arc::new(Mutex::new(Resources::new())),
+            bus: Arc::new(Mutex::new(bus)),
+            scheduler: Arc::new(Mutex::new(Scheduler::new())),
+            layout: LayoutTree::single(0),
+            debug: Debugger::new(),
+            remote,
+        }
+    }
+
+    pub fn add_system(&mut self, mut system: System<SystemState>) {
+        system.global = self.global.clone();
+        system.bus = self.bus.clone();
+        system.scheduler = self.scheduler.clone();
+        self.systems.insert(system.id(), system);
+    }
+
+    pub fn set_root(&mut self, id: SystemId) {
+        self.layout = LayoutTree::single(id);
+    }
+
+    pub async fn step(&mut self, input: Option<Event>, update: Option<TgUpdate>) -> Result<bool, EcsErrors> {
+        step(&mut self.terminal, &mut self.systems, &self.bus, &mut self.layout, &mut self.debug, true, input, update).await
+    }
+
+    /// False when root is finished
+    pub async fn input(&mut self, input: Event) -> bool {
+        self.step(Some(input), None).await.unwrap()
+    }
+
+    pub async fn update(&mut self, update: TgUpdate) -> bool {
+        self.step(None, Some(update)).await.unwrap()
+    }
+
+    pub async fn tick(&mut self) -> bool {
+        self.step(None, None).await.unwrap()
+    }
+
+    /// Waits for event of spawned task and steps with it, false when nothing came in 1 s
+    pub async fn remote(&mut self) -> bool {
+        match tokio::time::timeout(Duration::from_secs(1), self.remote.recv()).await {
+            Ok(Some(e)) => {
+                self.bus.lock().await.push(e);
+                self.tick().await
+            }
+            _ => false,
+        }
+    }
+
+    /// Timers due in `after` fire without sleeping, then step like `App::run`
+    pub async fn wait(&mut self, after: Duration) -> bool {
+        self.scheduler.lock().await.fire(Instant::now() + after, &mut *self.bus.lock().await);
+        let work = self.tick().await;
+        self.scheduler.lock().await.clear_fired();
+        work
+    }
+
+    pub async fn key(&mut self, code: KeyCode) -> bool {
+        self.input(Event::Key(KeyEvent::new(code, KeyModifiers::NONE))).await
+    }
+
+    /// Resize of terminal and its event, like real terminal does
+    pub async fn resize(&mut self, width: u16, height: u16) -> bool {
+        self.terminal.backend_mut().resize(width, height);
+        self.input(Event::Resize(width, height)).await
+    }
+
+    pub async fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.key(KeyCode::Char(c)).await;
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        self.terminal.backend().buffer()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let buf = self.buffer();
+        buf.content
+            .chunks(buf.area.width as usize)
+            .map(|row| row.iter().map(|c| c.symbol.as_str()).collect())
+            .collect()
+    }
+
+    pub fn contains(&self, text: &str) -> bool {
+        self.lines().iter().any(|l| l.contains(text))
+    }
+
+    pub fn state(&self, id: SystemId) -> &SystemState {
+        &self.systems[&id].state
+    }
+
+    pub fn state_is<S: Key + 'static>(&self, id: SystemId, state: S) -> bool {
+        Key::eq(self.state(id).as_ref(), &state)
+    }
+}
@@ -0,0 +1,242 @@
+use crossterm::event::{Event, KeyCode};
+use tui::{
+    layout::{Constraint, Direction},
+    widgets::Paragraph,
+};
+
+use super::bus::Delivery;
+use super::testing::Harness;
+use super::*;
+
+#[derive(Debug, Clone, SystemState)]
+enum Door {
+    Closed,
+    Open,
+    Gone,
+}
+
+#[derive(Default, System)]
+#[system(state = "Door", init = "Closed", end = "Gone")]
+#[drawer(Closed = "draw_closed", Open = "draw_open")]
+#[resolver(Closed = "resolve_closed", Open = "resolve_open")]
+struct DoorSystem {
+    opened: u32,
+}
+
+fn pressed(input: Option<Event>) -> Option<char> {
+    match input {
+        Some(Event::Key(k)) => match k.code {
+            KeyCode::Char(c) => Some(c),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl DoorSystem {
+    async fn draw_closed(&self, arg: &ArgumentDrawer<'_>) {
+        arg.frame.lock().await.render_widget(Paragraph::new("closed"), arg.area);
+    }
+
+    async fn draw_open(&self, arg: &ArgumentDrawer<'_>) {
+        let text = format!("open {}", self.opened);
+        arg.frame.lock().await.render_widget(Paragraph::new(text), arg.area);
+    }
+
+    async fn resolve_closed(&mut self, arg: ArgumentResolver) -> Door {
+        match pressed(arg.input) {
+            Some('o') => {
+                self.opened += 1;
+                Door::Open
+            }
+            _ => Door::Closed,
+        }
+    }
+
+    async fn resolve_open(&mut self, arg: ArgumentResolver) -> Door {
+        match pressed(arg.input) {
+            Some('c') => Door::Closed,
+            Some('q') => Door::Gone,
+            _ => Door::Open,
+        }
+    }
+}
+
+fn door(h: &Harness, id: SystemId) -> System<SystemState> {
+    System::from_struct(id, DoorSystem::default(), h.global.clone())
+}
+
+#[tokio::test]
+async fn draws_and_resolves_by_input() {
+    let mut h = Harness::new(30, 8);
+    h.add_system(door(&h, 1));
+    h.set_root(1);
+    assert!(h.tick().await);
+    assert!(h.contains("closed"));
+
+    assert!(h.key(KeyCode::Char('o')).await);
+    assert!(h.state_is(1, Door::Open));
+    assert!(h.contains("open 1"));
+}
+
+#[tokio::test]
+async fn finished_root_stops_and_resets() {
+    let mut h = Harness::new(30, 8);
+    h.add_system(door(&h, 1));
+    h.set_root(1);
+    h.key(KeyCode::Char('o')).await;
+    assert!(!h.key(KeyCode::Char('q')).await);
+    assert!(h.state_is(1, Door::Closed));
+}
+
+#[tokio::test]
+async fn input_goes_to_focused_pane() {
+    let mut h = Harness::new(60, 8);
+    h.add_system(door(&h, 1));
+    h.add_system(door(&h, 2));
+    h.layout = LayoutTree::new(LayoutNode::split(Direction::Horizontal, vec![
+        (Constraint::Percentage(50), LayoutNode::System(1)),
+        (Constraint::Percentage(50), LayoutNode::System(2)),
+    ]));
+
+    h.key(KeyCode::Char('o')).await;
+    assert!(h.state_is(1, Door::Open));
+    assert!(h.state_is(2, Door::Closed));
+    assert!(h.lines()[0].starts_with("open 1"));
+    assert!(h.lines()[0].contains("closed"));
+
+    h.key(KeyCode::Tab).await;
+    h.key(KeyCode::Char('o')).await;
+    assert!(h.state_is(2, Door::Open));
+
+    h.key(KeyCode::BackTab).await;
+    h.key(KeyCode::Char('c')).await;
+    assert!(h.state_is(1, Door::Closed));
+    assert!(h.state_is(2, Door::Open));
+}
+
+#[tokio::test]
+async fn transitions_are_recorded_and_shown() {
+    let mut h = Harness::new(60, 10);
+    h.add_system(door(&h, 1));
+    h.set_root(1);
+    h.key(KeyCode::Char('o')).await;
+    h.key(KeyCode::Char('c')).await;
+
+    let recent: Vec<_> = h.debug.recent().map(|t| (t.system, format!("{:?}->{:?}", t.from, t.to))).collect();
+    assert_eq!(recent, vec![(1, "Closed->Open".to_string()), (1, "Open->Closed".to_string())]);
+    // В журнале вид события, не нажатая клавиша
+    assert!(h.debug.recent().all(|t| t.cause == "key"));
+
+    h.key(KeyCode::F(12)).await;
+    assert!(h.debug.visible());
+    assert!(h.contains("Debug (F12)"));
+    assert!(h.contains("1:Closed"));
+    assert!(h.state_is(1, Door::Closed));
+}
+
+#[tokio::test]
+async fn events_are_delivered_after_step() {
+    let mut h = Harness::new(30, 8);
+    let mut s = door(&h, 1);
+    s.on_event::<u32, _>(|system, n| {
+        let n = *n;
+        Box::pin(async move {
+            system.local.lock().await.insert(n);
+        })
+    });
+    h.add_system(s);
+    h.set_root(1);
+    h.bus.lock().await.publish(7, 5u32);
+    h.tick().await;
+
+    assert_eq!(h.systems[&1].local.lock().await.get::<u32>(), Some(&5));
+    assert_eq!(h.bus.lock().await.deliveries(), &[Delivery { event: "u32", from: 7, to: 1 }]);
+}
+
+#[tokio::test]
+async fn small_terminal_shows_guard_and_drops_input() {
+    let mut h = Harness::new(30, 8);
+    h.add_system(door(&h, 1));
+    h.set_root(1);
+    h.resize(29, 7).await;
+    assert!(h.contains("too small: 29x7"), "{:?}", h.lines());
+    assert!(!h.contains("closed"));
+
+    h.key(KeyCode::Char('o')).await;
+    assert!(h.state_is(1, Door::Closed));
+
+    h.resize(30, 8).await;
+    assert!(h.contains("closed"));
+    h.key(KeyCode::Char('o')).await;
+    assert!(h.state_is(1, Door::Open));
+}
+
+fn stores_number<'a>(_system: &'a mut System<SystemState>, arg: ArgumentResolver) -> handler::ResolverFuture<'a, SystemState> {
+    Box::pin(async move {
+        // Глобальные ресурсы не заблокированы на время резолвера
+        arg.global.lock().await.insert(7u32);
+        Ok(Box::new(Door::Open) as SystemState)
+    })
+}
+
+#[tokio::test]
+async fn resolver_can_lock_global() {
+    let mut h = Harness::new(30, 8);
+    let mut s = door(&h, 1);
+    s.set_resolver(Box::new(Door::Closed), stores_number);
+    h.add_system(s);
+    h.set_root(1);
+    let step = h.step(None, None);
+    assert!(tokio::time::timeout(std::time::Duration::from_secs(1), step).await.unwrap().unwrap());
+    assert_eq!(h.global.lock().await.get::<u32>(), Some(&7));
+}
+
+fn opens_on_resize<'a>(_system: &'a mut System<SystemState>, arg: ArgumentResolver) -> handler::ResolverFuture<'a, SystemState> {
+    let open = matches!(arg.input, Some(Event::Resize(..)));
+    Box::pin(async move { Ok(Box::new(if open { Door::Open } else { Door::Closed }) as SystemState) })
+}
+
+#[tokio::test]
+async fn resize_goes_to_all_panes() {
+    let mut h = Harness::new(60, 8);
+    for id in [1, 2] {
+        let mut s = door(&h, id);
+        s.set_resolver(Box::new(Door::Closed), opens_on_resize);
+        h.add_system(s);
+    }
+    h.layout = LayoutTree::new(LayoutNode::split(Direction::Horizontal, vec![
+        (Constraint::Percentage(50), LayoutNode::System(1)),
+        (Constraint::Percentage(50), LayoutNode::System(2)),
+    ]));
+    h.step(Some(Event::Resize(60, 8)), None).await.unwrap();
+    assert!(h.state_is(1, Door::Open));
+    assert!(h.state_is(2, Door::Open));
+}
+
+#[test]
+fn passive_pane_is_never_focused() {
+    let mut layout = LayoutTree::new(LayoutNode::vertical(vec![
+        (Constraint::Min(0), LayoutNode::System(1)),
+        (Constraint::Length(1), LayoutNode::System(2)),
+    ]))
+    .with_passive(2);
+    assert_eq!(layout.focused(), Some(1));
+    assert!(!layout.on_input(&Event::Key(KeyCode::Tab.into())));
+    assert_eq!(layout.panes(), vec![1, 2]);
+}
+
+#[tokio::test]
+async fn subsystem_cycle_is_error() {
+    let mut h = Harness::new(30, 8);
+    for (id, sub) in [(1, 2), (2, 1)] {
+        let mut s = door(&h, id);
+        s.set_subsystem(Box::new(Door::Closed), sub);
+        h.add_system(s);
+    }
+    h.set_root(1);
+    match h.step(None, None).await {
+        Err(EcsErrors::SubsystemCycle(stack)) => assert_eq!(stack, vec![1, 2, 1]),
+        r => panic!("expected SubsystemCycle, got {:?}", r.map_err(|e| e.to_string())),
+    }
+}
@@ -0,0 +1,139 @@
+use std::{cmp::Reverse, collections::BinaryHeap, time::Duration};
+
+use tokio::time::Instant;
+
+use super::{EventBus, SystemId};
+
+pub type TimerId = u64;
+
+/// Published to `EventBus` when timer fires, also given to resolver of system in `ArgumentResolver::timers`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wakeup {
+    pub timer: TimerId,
+    pub system: SystemId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Timer {
+    at: Instant,
+    id: TimerId,
+    system: SystemId,
+}
+
+/// One-shot wake-ups of systems, `App::run` sleeps until `next_deadline`
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    timers: BinaryHeap<Reverse<Timer>>,
+    cancelled: Vec<TimerId>,
+    fired: Vec<Wakeup>,
+    next_id: TimerId,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn once(&mut self, system: SystemId, after: Duration) -> TimerId {
+        self.next_id += 1;
+        self.timers.push(Reverse(Timer {
+            at: Instant::now() + after,
+            id: self.next_id,
+            system,
+        }));
+        self.next_id
+    }
+
+    /// Unknown and already fired timers are ignored
+    pub fn cancel(&mut self, id: TimerId) {
+        if !self.cancelled.contains(&id) && self.timers.iter().any(|Reverse(t)| t.id == id) {
+            self.cancelled.push(id);
+        }
+    }
+
+    pub fn next_deadline(&mut self) -> Option<Instant> {
+        while let Some(Reverse(t)) = self.timers.peek() {
+            if let Some(i) = self.cancelled.iter().position(|c| *c == t.id) {
+                self.cancelled.swap_remove(i);
+                self.timers.pop();
+            } else {
+                return Some(t.at);
+            }
+        }
+        None
+    }
+
+    /// Move expired timers to fired and publish `Wakeup` for them
+    pub fn fire(&mut self, now: Instant, bus: &mut EventBus) {
+        while let Some(at) = self.next_deadline() {
+            if at > now {
+                break;
+            }
+            let Reverse(t) = self.timers.pop().unwrap();
+            let w = Wakeup {
+                timer: t.id,
+                system: t.system,
+            };
+            self.fired.push(w);
+            bus.publish(t.system, w);
+        }
+    }
+
+    pub fn take_fired(&mut self, system: SystemId) -> Vec<TimerId> {
+        let mut ids = Vec::new();
+        self.fired.retain(|w| {
+            if w.system == system {
+                ids.push(w.timer);
+                false
+            } else {
+                true
+            }
+        });
+        ids
+    }
+
+    pub fn clear_fired(&mut self) {
+        self.fired.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn once_fires_after_deadline() {
+        let mut s = Scheduler::new();
+        let mut bus = EventBus::new();
+        let start = Instant::now();
+        let id = s.once(3, SECOND);
+        assert!(s.next_deadline().unwrap() >= start + SECOND);
+
+        s.fire(start, &mut bus);
+        assert!(s.take_fired(3).is_empty());
+        s.fire(start + 2 * SECOND, &mut bus);
+        assert_eq!(s.take_fired(3), vec![id]);
+        assert_eq!(bus.pending(), 1);
+        assert_eq!(s.next_deadline(), None);
+    }
+
+    #[test]
+    fn cancel_ignores_unknown_and_fired() {
+        let mut s = Scheduler::new();
+        let mut bus = EventBus::new();
+        let fired = s.once(1, Duration::ZERO);
+        s.fire(Instant::now(), &mut bus);
+        s.cancel(fired);
+        s.cancel(42);
+        assert!(s.cancelled.is_empty());
+
+        let id = s.once(1, SECOND);
+        s.cancel(id);
+        s.cancel(id);
+        assert_eq!(s.cancelled, vec![id]);
+        assert_eq!(s.next_deadline(), None);
+        assert!(s.cancelled.is_empty());
+    }
+}
@@ -0,0 +1,430 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::{AppConfig, ConfigError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    DialogList,
+    ChatView,
+    Composer,
+    Popup,
+}
+
+impl FromStr for Context {
+    type Err = ConfigError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "dialogs" => Context::DialogList,
+            "chat" => Context::ChatView,
+            "composer" => Context::Composer,
+            "popup" => Context::Popup,
+            s => return Err(ConfigError::Invalid(format!("unknown keymap context {:?}", s))),
+        })
+    }
+}
+
+impl Context {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Context::DialogList => "dialogs",
+            Context::ChatView => "chat",
+            Context::Composer => "composer",
+            Context::Popup => "popup",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Top,
+    Bottom,
+    PageUp,
+    PageDown,
+    Open,
+    Back,
+    Quit,
+    Confirm,
+    Cancel,
+    DeleteChar,
+    Archive,
+    Unarchive,
+    MuteHour,
+    MuteEightHours,
+    MuteForever,
+    Unmute,
+    ToggleChatNotify,
+    ToggleNotify,
+}
+
+impl FromStr for Action {
+    type Err = ConfigError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "up" => Action::Up,
+            "down" => Action::Down,
+            "top" => Action::Top,
+            "bottom" => Action::Bottom,
+            "page_up" => Action::PageUp,
+            "page_down" => Action::PageDown,
+            "open" => Action::Open,
+            "back" => Action::Back,
+            "quit" => Action::Quit,
+            "confirm" => Action::Confirm,
+            "cancel" => Action::Cancel,
+            "delete_char" => Action::DeleteChar,
+            "archive" => Action::Archive,
+            "unarchive" => Action::Unarchive,
+            "mute_hour" => Action::MuteHour,
+            "mute_8h" => Action::MuteEightHours,
+            "mute_forever" => Action::MuteForever,
+            "unmute" => Action::Unmute,
+            "toggle_chat_notify" => Action::ToggleChatNotify,
+            "toggle_notify" => Action::ToggleNotify,
+            s => return Err(ConfigError::Invalid(format!("unknown action {:?}", s))),
+        })
+    }
+}
+
+/// Key without shift for chars, `A` and `shift-a` is the same key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// Shift of char is moved into the char: terminals send `A` or `shift-a`
+    fn new(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                let mut upper = c.to_uppercase();
+                let code = match (upper.next(), upper.next()) {
+                    (Some(u), None) => KeyCode::Char(u),
+                    _ => code,
+                };
+                Key { code, modifiers }
+            }
+            code => Key { code, modifiers },
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(k: KeyEvent) -> Self {
+        Key::new(k.code, k.modifiers)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ConfigError;
+    /// Format: `ctrl-x`, `C-x`, `alt-<`, `M-<`, `Enter`, `PageUp`, `j`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let (m, r) = match rest.split_once('-') {
+                Some((m, r)) if !r.is_empty() => (m, r),
+                _ => break,
+            };
+            match m {
+                "ctrl" | "C" => modifiers.insert(KeyModifiers::CONTROL),
+                "alt" | "M" => modifiers.insert(KeyModifiers::ALT),
+                "shift" | "S" => modifiers.insert(KeyModifiers::SHIFT),
+                _ => break,
+            }
+            rest = r;
+        }
+        let code = match rest {
+            "Enter" => KeyCode::Enter,
+            "Esc" => KeyCode::Esc,
+            "Tab" => KeyCode::Tab,
+            "BackTab" => KeyCode::BackTab,
+            "Backspace" => KeyCode::Backspace,
+            "Delete" => KeyCode::Delete,
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "PageUp" => KeyCode::PageUp,
+            "PageDown" => KeyCode::PageDown,
+            "Space" => KeyCode::Char(' '),
+            r if r.chars().count() == 1 => KeyCode::Char(r.chars().next().unwrap()),
+            r => return Err(ConfigError::Invalid(format!("unknown key {:?} in {:?}", r, s))),
+        };
+        Ok(Key::new(code, modifiers))
+    }
+}
+
+/// Sequence separated by spaces, `g g` or `C-x C-c`
+pub fn parse_sequence(s: &str) -> Result<Vec<Key>, ConfigError> {
+    let seq = s.split_whitespace().map(Key::from_str).collect::<Result<Vec<_>, _>>()?;
+    if seq.is_empty() {
+        return Err(ConfigError::Invalid(String::from("empty key sequence")));
+    }
+    Ok(seq)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyResult {
+    Action(Action),
+    /// Sequence started, wait for next key
+    Pending,
+    /// Not bound, for composer and text inputs it is text
+    Unbound(Key),
+}
+
+type Bindings = HashMap<Context, HashMap<Vec<Key>, Action>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: Bindings,
+    pending: Vec<Key>,
+}
+
+const COMMON: &[(Context, &str, Action)] = &[
+    (Context::DialogList, "Up", Action::Up),
+    (Context::DialogList, "Down", Action::Down),
+    (Context::DialogList, "Home", Action::Top),
+    (Context::DialogList, "End", Action::Bottom),
+    (Context::DialogList, "PageUp", Action::PageUp),
+    (Context::DialogList, "PageDown", Action::PageDown),
+    (Context::DialogList, "Enter", Action::Open),
+    (Context::DialogList, "Esc", Action::Back),
+    (Context::ChatView, "Up", Action::Up),
+    (Context::ChatView, "Down", Action::Down),
+    (Context::ChatView, "PageUp", Action::PageUp),
+    (Context::ChatView, "PageDown", Action::PageDown),
+    (Context::ChatView, "Esc", Action::Back),
+    (Context::Composer, "Enter", Action::Confirm),
+    (Context::Composer, "Backspace", Action::DeleteChar),
+    (Context::Composer, "Esc", Action::Cancel),
+    (Context::Popup, "Enter", Action::Confirm),
+    (Context::Popup, "Backspace", Action::DeleteChar),
+    (Context::Popup, "Esc", Action::Cancel),
+];
+
+const DEFAULT: &[(Context, &str, Action)] = &[
+    (Context::DialogList, "q", Action::Quit),
+    (Context::DialogList, "a", Action::Archive),
+    (Context::DialogList, "u", Action::Unmute),
+    (Context::DialogList, "1", Action::MuteHour),
+    (Context::DialogList, "8", Action::MuteEightHours),
+    (Context::DialogList, "m", Action::MuteForever),
+    (Context::DialogList, "n", Action::ToggleChatNotify),
+    (Context::DialogList, "N", Action::ToggleNotify),
+];
+
+const VIM: &[(Context, &str, Action)] = &[
+    (Context::DialogList, "k", Action::Up),
+    (Context::DialogList, "j", Action::Down),
+    (Context::DialogList, "g g", Action::Top),
+    (Context::DialogList, "G", Action::Bottom),
+    (Context::DialogList, "C-u", Action::PageUp),
+    (Context::DialogList, "C-d", Action::PageDown),
+    (Context::DialogList, "l", Action::Open),
+    (Context::DialogList, "h", Action::Back),
+    (Context::DialogList, "q", Action::Quit),
+    (Context::DialogList, "d a", Action::Archive),
+    (Context::DialogList, "d u", Action::Unarchive),
+    (Context::DialogList, "z 1", Action::MuteHour),
+    (Context::DialogList, "z 8", Action::MuteEightHours),
+    (Context::DialogList, "z z", Action::MuteForever),
+    (Context::DialogList, "z u", Action::Unmute),
+    (Context::DialogList, "z n", Action::ToggleChatNotify),
+    (Context::DialogList, "z N", Action::ToggleNotify),
+    (Context::ChatView, "k", Action::Up),
+    (Context::ChatView, "j", Action::Down),
+    (Context::ChatView, "g g", Action::Top),
+    (Context::ChatView, "G", Action::Bottom),
+    (Context::ChatView, "C-u", Action::PageUp),
+    (Context::ChatView, "C-d", Action::PageDown),
+    (Context::ChatView, "h", Action::Back),
+];
+
+const EMACS: &[(Context, &str, Action)] = &[
+    (Context::DialogList, "C-p", Action::Up),
+    (Context::DialogList, "C-n", Action::Down),
+    (Context::DialogList, "M-<", Action::Top),
+    (Context::DialogList, "M->", Action::Bottom),
+    (Context::DialogList, "M-v", Action::PageUp),
+    (Context::DialogList, "C-v", Action::PageDown),
+    (Context::DialogList, "C-g", Action::Back),
+    (Context::DialogList, "C-x C-c", Action::Quit),
+    (Context::DialogList, "C-c a", Action::Archive),
+    (Context::DialogList, "C-c u", Action::Unarchive),
+    (Context::DialogList, "C-c m 1", Action::MuteHour),
+    (Context::DialogList, "C-c m 8", Action::MuteEightHours),
+    (Context::DialogList, "C-c m m", Action::MuteForever),
+    (Context::DialogList, "C-c m u", Action::Unmute),
+    (Context::DialogList, "C-c n", Action::ToggleChatNotify),
+    (Context::DialogList, "C-c N", Action::ToggleNotify),
+    (Context::ChatView, "C-p", Action::Up),
+    (Context::ChatView, "C-n", Action::Down),
+    (Context::ChatView, "M-<", Action::Top),
+    (Context::ChatView, "M->", Action::Bottom),
+    (Context::ChatView, "M-v", Action::PageUp),
+    (Context::ChatView, "C-v", Action::PageDown),
+    (Context::ChatView, "C-g", Action::Back),
+    (Context::Composer, "C-g", Action::Cancel),
+    (Context::Composer, "C-h", Action::DeleteChar),
+    (Context::Popup, "C-g", Action::Cancel),
+];
+
+pub const PRESETS: &[&str] = &["default", "vim", "emacs"];
+
+impl Keymap {
+    pub fn preset(name: &str) -> Result<Self, ConfigError> {
+        let preset = match name {
+            "default" => DEFAULT,
+            "vim" => VIM,
+            "emacs" => EMACS,
+            n => return Err(ConfigError::Invalid(format!("unknown keymap preset {:?}, expected one of {:?}", n, PRESETS))),
+        };
+        let mut keymap = Keymap::default();
+        for (ctx, keys, action) in COMMON.iter().chain(preset.iter()) {
+            keymap.bind(*ctx, parse_sequence(keys)?, *action);
+        }
+        Ok(keymap)
+    }
+
+    /// Preset from `keymap` and overrides from `[keybindings.<context>]`
+    pub fn from_config(config: &AppConfig) -> Result<Self, ConfigError> {
+        let mut keymap = Self::preset(&config.keymap)?;
+        for (ctx, binds) in config.keybindings.iter() {
+            let ctx = Context::from_str(ctx)?;
+            for (keys, action) in binds.iter() {
+                keymap.bind(ctx, parse_sequence(keys)?, Action::from_str(action)?);
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn bind(&mut self, ctx: Context, keys: Vec<Key>, action: Action) {
+        self.bindings.entry(ctx).or_default().insert(keys, action);
+    }
+
+    pub fn feed(&mut self, ctx: Context, key: KeyEvent) -> KeyResult {
+        let key = Key::from(key);
+        let binds = match self.bindings.get(&ctx) {
+            Some(b) => b,
+            None => return KeyResult::Unbound(key),
+        };
+        self.pending.push(key);
+        if let Some(a) = binds.get(&self.pending) {
+            self.pending.clear();
+            return KeyResult::Action(*a);
+        }
+        if binds.keys().any(|seq| seq.starts_with(&self.pending)) {
+            return KeyResult::Pending;
+        }
+        // Начатая последовательность не совпала, пробуем клавишу отдельно
+        let restart = self.pending.len() > 1;
+        self.pending.clear();
+        if restart {
+            self.feed(ctx, KeyEvent::new(key.code, key.modifiers))
+        } else {
+            KeyResult::Unbound(key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    fn ch(c: char) -> KeyEvent {
+        key(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn parses_keys_and_sequences() {
+        assert_eq!(
+            Key::from_str("C-x").unwrap(),
+            Key { code: KeyCode::Char('x'), modifiers: KeyModifiers::CONTROL }
+        );
+        assert_eq!(
+            Key::from_str("alt-<").unwrap(),
+            Key { code: KeyCode::Char('<'), modifiers: KeyModifiers::ALT }
+        );
+        assert_eq!(Key::from_str("PageUp").unwrap().code, KeyCode::PageUp);
+        assert_eq!(Key::from_str("-").unwrap().code, KeyCode::Char('-'));
+        assert_eq!(parse_sequence("g g").unwrap().len(), 2);
+        assert!(parse_sequence(" ").is_err());
+        assert!(Key::from_str("Hyper-x").is_err());
+        assert!(Key::from_str("F13").is_err());
+    }
+
+    #[test]
+    fn shift_is_uppercase() {
+        let upper = Key::from_str("A").unwrap();
+        assert_eq!(Key::from_str("shift-a").unwrap(), upper);
+        assert_eq!(Key::from_str("S-A").unwrap(), upper);
+        assert_eq!(Key::from(key(KeyCode::Char('a'), KeyModifiers::SHIFT)), upper);
+        assert_eq!(Key::from(key(KeyCode::Char('A'), KeyModifiers::SHIFT)), upper);
+        assert_eq!(Key::from(ch('A')), upper);
+        assert_ne!(Key::from(ch('a')), upper);
+        // Shift не у символов сохраняется
+        assert_eq!(Key::from(key(KeyCode::Tab, KeyModifiers::SHIFT)).modifiers, KeyModifiers::SHIFT);
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for name in PRESETS {
+            let mut k = Keymap::preset(name).unwrap();
+            assert_eq!(k.feed(Context::DialogList, key(KeyCode::Enter, KeyModifiers::NONE)), KeyResult::Action(Action::Open));
+        }
+        assert!(Keymap::preset("nano").is_err());
+
+        let mut k = Keymap::preset("default").unwrap();
+        assert_eq!(k.feed(Context::DialogList, key(KeyCode::Char('n'), KeyModifiers::SHIFT)), KeyResult::Action(Action::ToggleNotify));
+        assert_eq!(k.feed(Context::DialogList, ch('n')), KeyResult::Action(Action::ToggleChatNotify));
+        assert_eq!(k.feed(Context::Popup, key(KeyCode::Esc, KeyModifiers::NONE)), KeyResult::Action(Action::Cancel));
+    }
+
+    #[test]
+    fn sequences_wait_and_restart() {
+        let mut k = Keymap::preset("vim").unwrap();
+        assert_eq!(k.feed(Context::DialogList, ch('g')), KeyResult::Pending);
+        assert_eq!(k.feed(Context::DialogList, ch('g')), KeyResult::Action(Action::Top));
+
+        // Не совпавшая последовательность: клавиша разбирается отдельно
+        assert_eq!(k.feed(Context::DialogList, ch('z')), KeyResult::Pending);
+        assert_eq!(k.feed(Context::DialogList, ch('j')), KeyResult::Action(Action::Down));
+        assert_eq!(k.feed(Context::DialogList, ch('x')), KeyResult::Unbound(Key::from(ch('x'))));
+
+        let mut k = Keymap::preset("emacs").unwrap();
+        let cx = key(KeyCode::Char('x'), KeyModifiers::CONTROL);
+        let cc = key(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(k.feed(Context::DialogList, cx), KeyResult::Pending);
+        assert_eq!(k.feed(Context::DialogList, cc), KeyResult::Action(Action::Quit));
+        assert_eq!(k.feed(Context::DialogList, cc), KeyResult::Pending);
+        assert_eq!(k.feed(Context::DialogList, ch('m')), KeyResult::Pending);
+        assert_eq!(k.feed(Context::DialogList, ch('8')), KeyResult::Action(Action::MuteEightHours));
+    }
+
+    #[test]
+    fn config_overrides_preset() {
+        let mut conf = AppConfig {
+            keymap: String::from("vim"),
+            ..Default::default()
+        };
+        conf.keybindings.entry(String::from("dialogs")).or_default().insert(String::from("shift-x"), String::from("archive"));
+        let mut k = Keymap::from_config(&conf).unwrap();
+        assert_eq!(k.feed(Context::DialogList, ch('X')), KeyResult::Action(Action::Archive));
+        assert_eq!(k.feed(Context::DialogList, ch('j')), KeyResult::Action(Action::Down));
+
+        conf.keybindings.entry(String::from("dialogs")).or_default().insert(String::from("y"), String::from("yank"));
+        assert!(Keymap::from_config(&conf).is_err());
+    }
+}
@@ -1,40 +1,120 @@
 use grammers_client::{Config, InitParams};
 use grammers_session::Session;
 use clap::Parser;
-use dirs::home_dir;
+use std::{fs, io, path::Path, sync::{Arc, Mutex}};
+use tui::{backend::Backend, layout::Constraint, Terminal};
 
 mod app;
 mod tg;
 mod args;
+mod config;
 mod dialogs;
+mod keymap;
+mod notify;
+mod theme;
 mod ecs;
 mod systems;
-// mod di;
+mod text;
+
+/// Transitions of systems are written with `tracing`, terminal is busy with UI
+fn init_log(path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", tg::report(&e));
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run() -> Result<(), tg::TgErrors> {
     let arg = args::Arguments::parse();
-    let path = arg.session_path.clone().unwrap_or_else(||{
-        let mut h = home_dir().unwrap();
-        h.push(".config");
-        h.push("teleconsole");
-        h.push("session");
-        h
-    });
-    println!("Arguments {:?}", arg);
-    println!("Session path: {:?}", path);
+    let conf = config::AppConfig::load(arg.config.as_deref())?.merge_args(&arg);
+    conf.validate()?;
+    if let Some(args::Commands::Config(args::ConfigCommand::Check)) = arg.command {
+        print!("{}", conf.to_toml()?);
+        return Ok(());
+    }
+    let keymap = keymap::Keymap::from_config(&conf)?;
+    let theme = theme::Theme::from_config(&conf)?;
+    if !conf.log_file.as_os_str().is_empty() {
+        if let Err(e) = init_log(&conf.log_file) {
+            eprintln!("Can`t open log {:?}: {}", conf.log_file, e);
+        }
+    }
+    let path = conf.session_path.clone();
+    tracing::debug!(session = ?path, demo = arg.demo, "starting");
 
-    let config = Config {
-        api_hash: arg.api_hash,
-        api_id: arg.api_id,
-        params: InitParams::default(),
-        session: Session::load_file_or_create(path.clone()).unwrap(),
-    };
-    if let Ok(mut a) = app::App::new(config, path).await {
-        let mut ls = ecs::System::new(1, systems::login::LoginState::PreLogin, systems::login::LoginState::EndLogin, a.get_global());
-        a.add_system(ls);
-        a.run().await;
+    let client: Arc<dyn tg::TgClient> = if arg.demo {
+        Arc::new(tg::FakeClient::demo())
     } else {
-        eprintln!("Can`t start app");
+        let session = Session::load_file_or_create(&path).map_err(|source| tg::TgErrors::SessionIo { path, source })?;
+        let config = Config {
+            api_hash: conf.api_hash.clone(),
+            api_id: conf.api_id,
+            params: InitParams::default(),
+            session,
+        };
+        Arc::new(tg::GrammersClient::connect(config).await?)
+    };
+    match arg.backend {
+        #[cfg(feature = "backend-crossterm")]
+        args::Backend::Crossterm => {
+            let terminal = Terminal::new(tui::backend::CrosstermBackend::new(io::stdout()));
+            start(terminal, client, conf, keymap, theme).await
+        }
+        #[cfg(feature = "backend-termion")]
+        args::Backend::Termion => {
+            let terminal = Terminal::new(tui::backend::TermionBackend::new(io::stdout()));
+            start(terminal, client, conf, keymap, theme).await
+        }
+    }
+}
+
+async fn start<B: Backend>(
+    terminal: io::Result<Terminal<B>>,
+    client: Arc<dyn tg::TgClient>,
+    conf: config::AppConfig,
+    keymap: keymap::Keymap,
+    theme: theme::Theme,
+) -> Result<(), tg::TgErrors> {
+    // Ошибка сети не значит что вход нужен, она останавливает запуск
+    let authorized = client.is_authorized().await?;
+    // До входа имени нет, его сообщит Login
+    let me = if authorized { Some(client.me().await?) } else { None };
+    let mut a = app::App::new(client, terminal.map_err(tg::TgErrors::Terminal)?);
+    a.set_fps(conf.fps);
+    a.get_global().lock().await.insert(conf.notifications.notifier());
+    a.get_global().lock().await.insert(keymap);
+    a.get_global().lock().await.insert(theme);
+    a.get_global().lock().await.insert(conf);
+    if let Some(name) = me {
+        a.get_bus().lock().await.publish(app::APP_ID, systems::AccountChanged(name));
     }
+    let ls = systems::login::Login::system(1, a.get_global());
+    a.add_system(ls);
+    let status = systems::status::StatusBar::system(2, a.get_global());
+    a.add_system(status);
+    // Login открывается списком диалогов как подсистема
+    let dialogs = systems::dialog_list::DialogList::system(3, a.get_global(), authorized);
+    a.add_system(dialogs);
+    a.set_layout(
+        ecs::LayoutTree::new(ecs::LayoutNode::vertical(vec![
+            (Constraint::Min(0), ecs::LayoutNode::System(3)),
+            (Constraint::Length(1), ecs::LayoutNode::System(2)),
+        ]))
+        .with_passive(2),
+    );
+    Ok(a.run().await?)
 }
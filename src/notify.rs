@@ -0,0 +1,202 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use crossterm::{queue, terminal::SetTitle};
+
+use crate::text;
+use crate::tg::client::MessageInfo;
+
+const PREVIEW_LEN: usize = 64;
+const TITLE: &str = "teleconsole";
+
+/// Notifications about new messages for the case when teleconsole not in focus (tmux pane, other tab)
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    pub enabled: bool,
+    pub bell: bool,
+    pub title: bool,
+    /// Command called as `command <sender> <preview>`, for example `notify-send`
+    pub command: Option<String>,
+    disabled: HashSet<i64>,
+    open_chat: Option<i64>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Notifier {
+            enabled: true,
+            bell: true,
+            title: true,
+            command: None,
+            disabled: HashSet::new(),
+            open_chat: None,
+        }
+    }
+}
+
+/// Bell and title for terminal, kept in global resources and written by `App` between frames,
+/// so they are not mixed with drawing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Alerts {
+    pub bell: bool,
+    pub title: Option<String>,
+}
+
+impl Alerts {
+    /// Pending alerts are written and cleared
+    pub fn flush<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if std::mem::take(&mut self.bell) {
+            out.write_all(b"\x07")?;
+        }
+        if let Some(t) = self.title.take() {
+            queue!(out, SetTitle(t))?;
+        }
+        out.flush()
+    }
+}
+
+/// First line in `PREVIEW_LEN` columns
+fn preview(body: &str) -> String {
+    text::truncate(body.lines().next().unwrap_or(""), PREVIEW_LEN)
+}
+
+impl Notifier {
+    pub fn new(command: Option<String>) -> Self {
+        Notifier {
+            command,
+            ..Default::default()
+        }
+    }
+
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.enabled
+    }
+
+    /// Return true if notifications for chat enabled after toggle
+    pub fn toggle_chat(&mut self, id: i64) -> bool {
+        if !self.disabled.remove(&id) {
+            self.disabled.insert(id);
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn chat_enabled(&self, id: i64) -> bool {
+        !self.disabled.contains(&id)
+    }
+
+    pub fn set_open_chat(&mut self, id: Option<i64>) {
+        self.open_chat = id;
+    }
+
+    /// Muted chats are filtered by caller, it know about notify_settings
+    pub fn on_message(&self, message: &MessageInfo, chat: &str, muted: bool, alerts: &mut Alerts) -> io::Result<()> {
+        if !self.enabled
+            || muted
+            || message.outgoing
+            || !self.chat_enabled(message.chat)
+            || self.open_chat == Some(message.chat)
+        {
+            return Ok(());
+        }
+        if self.bell {
+            alerts.bell = true;
+        }
+        if let Some(cmd) = &self.command {
+            let sender = match &message.sender {
+                Some(s) if s != chat => format!("{} @ {}", s, chat),
+                _ => chat.to_string(),
+            };
+            let mut child = Command::new(cmd)
+                .arg(sender)
+                .arg(preview(&message.text))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            // Без wait завершённый процесс остаётся зомби
+            std::thread::spawn(move || child.wait());
+        }
+        Ok(())
+    }
+
+    pub fn set_unread(&self, unread: i32, alerts: &mut Alerts) {
+        if !self.title {
+            return;
+        }
+        alerts.title = Some(if unread > 0 {
+            format!("({}) {}", unread, TITLE)
+        } else {
+            TITLE.to_string()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn message(chat: i64, outgoing: bool) -> MessageInfo {
+        MessageInfo {
+            id: 1,
+            chat,
+            sender: Some(String::from("Alice")),
+            text: String::from("hi"),
+            outgoing,
+            date: Utc::now(),
+            media: false,
+        }
+    }
+
+    #[test]
+    fn bell_only_for_enabled_not_muted_incoming() {
+        let mut n = Notifier::default();
+        let mut alerts = Alerts::default();
+        n.on_message(&message(1, false), "Alice", true, &mut alerts).unwrap();
+        n.on_message(&message(1, true), "Alice", false, &mut alerts).unwrap();
+        n.set_open_chat(Some(1));
+        n.on_message(&message(1, false), "Alice", false, &mut alerts).unwrap();
+        n.set_open_chat(None);
+        n.toggle_chat(1);
+        n.on_message(&message(1, false), "Alice", false, &mut alerts).unwrap();
+        assert!(!alerts.bell);
+
+        n.on_message(&message(2, false), "Bob", false, &mut alerts).unwrap();
+        assert!(alerts.bell);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_is_called() {
+        let n = Notifier::new(Some(String::from("true")));
+        let mut alerts = Alerts::default();
+        n.on_message(&message(1, false), "Alice", false, &mut alerts).unwrap();
+        assert!(alerts.bell);
+
+        let n = Notifier::new(Some(String::from("/nonexistent/notify")));
+        assert!(n.on_message(&message(1, false), "Alice", false, &mut alerts).is_err());
+    }
+
+    #[test]
+    fn alerts_are_written_once() {
+        let n = Notifier::default();
+        let mut alerts = Alerts { bell: true, title: None };
+        n.set_unread(3, &mut alerts);
+        let mut out = Vec::new();
+        alerts.flush(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with('\x07'));
+        assert!(out.contains("(3) teleconsole"));
+
+        let mut out = Vec::new();
+        alerts.flush(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
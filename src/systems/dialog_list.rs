@@ -0,0 +1,688 @@
+use std::{sync::Arc, time::Duration};
+
+use crossterm::event::Event;
+use futures::Future;
+use tokio::sync::Mutex;
+
+use crate::dialogs::{ArchivedDialogs, DialogsSelected, OrderedDialogs, ARCHIVE_ID};
+use crate::ecs::{system_struct, ArgumentDrawer, ArgumentResolver, Resources, System, SystemId, SystemState, TimerId};
+use crate::keymap::{Action, Context, KeyResult, Keymap};
+use crate::notify::{Alerts, Notifier};
+use crate::systems::{self, ChatClosed, ChatOpened, Notice, OperationFinished, OperationStarted, Reconnected};
+use crate::theme::Theme;
+use crate::tg::{self, ChatId, ChatInfo, MuteFor, TgClient, TgErrors, TgUpdate};
+
+/// Messages of unknown chats in this time give one reload
+const RELOAD_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, SystemState)]
+pub enum DialogListState {
+    /// Login is shown until account is authorized
+    SignIn,
+    List,
+    Archive,
+    Closed,
+}
+
+/// Reply of request spawned by resolver
+#[derive(Debug, Clone)]
+enum Reply {
+    Dialogs { chats: Vec<ChatInfo>, archived: Vec<ChatInfo> },
+    Archive(Vec<ChatInfo>),
+    Archived(ChatId),
+    Unarchived(ChatId),
+    Muted { chat: ChatId, until: i32 },
+}
+
+/// Comes back to system as event, operation is shown in status bar until it
+struct Done {
+    operation: &'static str,
+    reply: Result<Reply, TgErrors>,
+}
+
+/// Main list of dialogs with pinned archive row and view of archived chats.
+/// Network requests are spawned, list is changed by their replies.
+#[derive(Default, System)]
+#[system(state = "DialogListState", init = "SignIn", end = "Closed")]
+#[drawer(List = "draw_list", Archive = "draw_archive")]
+#[resolver(SignIn = "resolve_sign_in", List = "resolve_list", Archive = "resolve_archive")]
+// Id системы Login из main
+#[subsystem(SignIn = 1)]
+pub struct DialogList {
+    pub chats: OrderedDialogs,
+    pub selected: DialogsSelected,
+    pub archive: ArchivedDialogs,
+    pub archive_selected: DialogsSelected,
+    /// Load dialogs on next resolve
+    need_load: bool,
+    /// Timer of delayed reload for unknown chats
+    reload: Option<TimerId>,
+    keymap: Option<Keymap>,
+    /// Unread in title of terminal
+    title_unread: Option<i32>,
+    /// Opened by Enter until Back or archive
+    open_chat: Option<ChatId>,
+    mode: Option<Context>,
+}
+
+impl DialogList {
+    /// Without login when account is already authorized
+    pub fn system(id: SystemId, global: Arc<Mutex<Resources>>, authorized: bool) -> System<SystemState> {
+        let list = DialogList {
+            need_load: authorized,
+            ..Default::default()
+        };
+        let mut s = System::from_struct(id, list, global);
+        if authorized {
+            s.state = Box::new(DialogListState::List);
+        }
+        s.on_event::<Done, _>(|system, done| {
+            Box::pin(async move {
+                let id = system.id();
+                system.bus.lock().await.publish(id, OperationFinished(done.operation));
+                match &done.reply {
+                    Ok(reply) => {
+                        if let Ok(list) = system_struct::<DialogList>(&system.local).await {
+                            list.lock().await.on_reply(reply.clone());
+                        }
+                    }
+                    Err(e) => system.bus.lock().await.publish(id, Notice::from(e)),
+                }
+            })
+        });
+        // Открытый чат виден пользователю, уведомлять о нём не нужно
+        s.on_event::<ChatOpened, _>(|system, e| {
+            let id = e.chat_id;
+            Box::pin(async move {
+                if let Some(n) = system.global.lock().await.get_mut::<Notifier>() {
+                    n.set_open_chat(Some(id));
+                }
+            })
+        });
+        s.on_event::<ChatClosed, _>(|system, _| {
+            Box::pin(async move {
+                if let Some(n) = system.global.lock().await.get_mut::<Notifier>() {
+                    n.set_open_chat(None);
+                }
+            })
+        });
+        // Разница могла быть неполной, список берётся с сервера заново
+        s.on_event::<Reconnected, _>(|system, _| {
+            Box::pin(async move {
+                if let Ok(list) = system_struct::<DialogList>(&system.local).await {
+                    list.lock().await.need_load = true;
+                }
+                system.scheduler.lock().await.once(system.id(), Duration::ZERO);
+            })
+        });
+        s
+    }
+
+    fn on_reply(&mut self, reply: Reply) {
+        match reply {
+            Reply::Dialogs { chats, archived } => {
+                self.chats.clear();
+                for d in chats {
+                    self.chats.insert(d);
+                }
+                self.chats.set_archive(&archived);
+                self.selected.shift(&self.chats.ids(), 0);
+                self.archive = ArchivedDialogs::new(archived);
+                self.archive_selected.shift(&self.archive.ids(), 0);
+            }
+            Reply::Archive(chats) => {
+                self.archive = ArchivedDialogs::new(chats);
+                self.archive_selected.shift(&self.archive.ids(), 0);
+            }
+            Reply::Archived(id) => {
+                if self.selected.selected == id {
+                    self.selected.next(&self.chats.ids());
+                }
+                self.chats.archive_chat(id);
+                // Последний в списке: выбираем предыдущий
+                self.selected.shift(&self.chats.ids(), 0);
+            }
+            Reply::Unarchived(id) => {
+                if self.archive_selected.selected == id {
+                    self.archive_selected.next(&self.archive.ids());
+                }
+                self.archive.remove(id);
+                self.archive_selected.shift(&self.archive.ids(), 0);
+                self.need_load = true;
+            }
+            Reply::Muted { chat, until } => {
+                self.chats.set_mute_until(chat, until);
+                self.archive.set_mute_until(chat, until);
+            }
+        }
+    }
+
+    /// Mute or unmute by key, other actions are ignored
+    async fn mute(&self, arg: &ArgumentResolver, action: Action, chat: ChatId) {
+        let until = match action {
+            Action::MuteHour => MuteFor::Hour.until(),
+            Action::MuteEightHours => MuteFor::EightHours.until(),
+            Action::MuteForever => MuteFor::Forever.until(),
+            Action::Unmute => 0,
+            _ => return,
+        };
+        self.request(arg, "mute", move |c| async move {
+            c.set_mute(chat, until).await?;
+            Ok(Reply::Muted { chat, until })
+        })
+        .await;
+    }
+
+    /// Update moves chat up in its list, new message is shown by notifier of global resources.
+    /// Chat which is in no list, e.g. new one, reloads them after `RELOAD_DELAY`.
+    async fn on_update(&mut self, arg: &ArgumentResolver) {
+        if self.reload.is_some_and(|t| arg.timers.contains(&t)) {
+            self.reload = None;
+            self.need_load = true;
+        }
+        let update = match arg.update.lock().await.clone() {
+            Some(u) => u,
+            None => return,
+        };
+        if !self.chats.apply(&update) {
+            if self.archive.apply(&update) {
+                self.chats.set_archive(self.archive.list());
+            } else if self.reload.is_none() {
+                self.reload = Some(arg.scheduler.lock().await.once(arg.id, RELOAD_DELAY));
+            }
+        }
+        if let TgUpdate::NewMessage(m) = &update {
+            let chat = self.chats.get(m.chat).or_else(|| self.archive.get(m.chat));
+            let (name, muted) = match chat {
+                Some(d) => (d.name.clone(), d.muted),
+                None => (m.sender.clone().unwrap_or_default(), false),
+            };
+            let mut global = arg.global.lock().await;
+            let notifier = global.get::<Notifier>().cloned().unwrap_or_default();
+            if let Some(alerts) = global.get_mut::<Alerts>() {
+                if let Err(e) = notifier.on_message(m, &name, muted, alerts) {
+                    drop(global);
+                    arg.bus.lock().await.publish(arg.id, Notice::error(format!("Notify command failed: {}", e)));
+                }
+            }
+        }
+    }
+
+    /// Title follows unread of not muted chats
+    async fn sync_title(&mut self, arg: &ArgumentResolver) {
+        let unread = self.chats.unread_total();
+        if self.title_unread == Some(unread) {
+            return;
+        }
+        self.title_unread = Some(unread);
+        let mut global = arg.global.lock().await;
+        let notifier = global.get::<Notifier>().cloned().unwrap_or_default();
+        if let Some(alerts) = global.get_mut::<Alerts>() {
+            notifier.set_unread(unread, alerts);
+        }
+    }
+
+    /// Notifications on or off, for all chats or for one
+    async fn toggle_notify(&self, arg: &ArgumentResolver, chat: Option<ChatId>) {
+        let mut global = arg.global.lock().await;
+        let notifier = match global.get_mut::<Notifier>() {
+            Some(n) => n,
+            None => return,
+        };
+        let text = match chat {
+            Some(id) if notifier.toggle_chat(id) => "Notifications for chat on",
+            Some(_) => "Notifications for chat off",
+            None if notifier.toggle() => "Notifications on",
+            None => "Notifications off",
+        };
+        drop(global);
+        arg.bus.lock().await.publish(arg.id, Notice::info(text));
+    }
+
+    async fn close_chat(&mut self, arg: &ArgumentResolver) {
+        if let Some(chat_id) = self.open_chat.take() {
+            arg.bus.lock().await.publish(arg.id, ChatClosed { chat_id });
+        }
+    }
+
+    /// Action of key in dialog list, keymap is copied from global on first key
+    async fn action(&mut self, arg: &ArgumentResolver) -> Option<Action> {
+        let key = match arg.input {
+            Some(Event::Key(k)) => k,
+            _ => return None,
+        };
+        if self.keymap.is_none() {
+            self.keymap = Some(systems::keymap(arg).await);
+        }
+        match self.keymap.as_mut()?.feed(Context::DialogList, key) {
+            KeyResult::Action(a) => Some(a),
+            KeyResult::Pending | KeyResult::Unbound(_) => None,
+        }
+    }
+
+    /// Request out of step, reply comes as `Done`
+    async fn request<F, Fut>(&self, arg: &ArgumentResolver, operation: &'static str, f: F)
+    where
+        F: FnOnce(Arc<dyn TgClient>) -> Fut,
+        Fut: Future<Output = Result<Reply, TgErrors>> + Send + 'static,
+    {
+        let client = match arg.resource::<Arc<dyn TgClient>>().await {
+            Ok(c) => c,
+            Err(e) => {
+                arg.bus.lock().await.publish(arg.id, Notice::error(e));
+                return;
+            }
+        };
+        arg.bus.lock().await.publish(arg.id, OperationStarted(operation));
+        let task = f(client);
+        arg.spawn(async move {
+            Done {
+                operation,
+                reply: task.await,
+            }
+        })
+        .await;
+    }
+
+    async fn load(&mut self, arg: &ArgumentResolver) {
+        self.need_load = false;
+        if let Some(t) = self.reload.take() {
+            arg.scheduler.lock().await.cancel(t);
+        }
+        self.request(arg, "dialogs", |c| async move {
+            let chats = c.dialogs().await?;
+            let archived = c.folder_dialogs(tg::ARCHIVE_FOLDER).await?;
+            Ok(Reply::Dialogs { chats, archived })
+        })
+        .await;
+    }
+
+    async fn load_archive(&mut self, arg: &ArgumentResolver) {
+        self.request(arg, "archive", |c| async move {
+            Ok(Reply::Archive(c.folder_dialogs(tg::ARCHIVE_FOLDER).await?))
+        })
+        .await;
+    }
+
+    async fn draw_list(&self, arg: &ArgumentDrawer<'_>) {
+        let theme = arg.global.lock().await.get::<Theme>().cloned().unwrap_or_default();
+        let mut selected = self.selected.clone();
+        arg.frame
+            .lock()
+            .await
+            .render_stateful_widget(self.chats.clone().with_theme(theme), arg.area, &mut selected);
+    }
+
+    async fn draw_archive(&self, arg: &ArgumentDrawer<'_>) {
+        let theme = arg.global.lock().await.get::<Theme>().cloned().unwrap_or_default();
+        let mut selected = self.archive_selected.clone();
+        arg.frame
+            .lock()
+            .await
+            .render_stateful_widget(self.archive.clone().with_theme(theme), arg.area, &mut selected);
+    }
+
+    /// Login finished: by sign in or by cancel
+    async fn resolve_sign_in(&mut self, arg: ArgumentResolver) -> DialogListState {
+        if arg.finished.is_none() {
+            return DialogListState::SignIn;
+        }
+        let authorized = match arg.resource::<Arc<dyn TgClient>>().await {
+            Ok(c) => c.is_authorized().await.unwrap_or(false),
+            Err(_) => false,
+        };
+        if !authorized {
+            return DialogListState::Closed;
+        }
+        systems::set_mode(&arg, &mut self.mode, Context::DialogList).await;
+        self.load(&arg).await;
+        DialogListState::List
+    }
+
+    async fn resolve_list(&mut self, arg: ArgumentResolver) -> DialogListState {
+        systems::set_mode(&arg, &mut self.mode, Context::DialogList).await;
+        self.on_update(&arg).await;
+        if self.need_load {
+            self.load(&arg).await;
+        }
+        self.sync_title(&arg).await;
+        let action = match self.action(&arg).await {
+            Some(a) => a,
+            None => return DialogListState::List,
+        };
+        if self.selected.navigate(&self.chats.ids(), action) {
+            return DialogListState::List;
+        }
+        let selected = self.selected.selected;
+        match action {
+            Action::Open if selected == ARCHIVE_ID => {
+                self.close_chat(&arg).await;
+                self.load_archive(&arg).await;
+                return DialogListState::Archive;
+            }
+            Action::Open if self.chats.get(selected).is_some() => {
+                self.open_chat = Some(selected);
+                arg.bus.lock().await.publish(arg.id, ChatOpened { chat_id: selected });
+            }
+            Action::Archive if self.chats.get(selected).is_some() => {
+                self.request(&arg, "archiving", move |c| async move {
+                    c.archive(selected).await?;
+                    Ok(Reply::Archived(selected))
+                })
+                .await;
+            }
+            Action::MuteHour | Action::MuteEightHours | Action::MuteForever | Action::Unmute
+                if self.chats.get(selected).is_some() =>
+            {
+                self.mute(&arg, action, selected).await;
+            }
+            Action::ToggleChatNotify if self.chats.get(selected).is_some() => self.toggle_notify(&arg, Some(selected)).await,
+            Action::ToggleNotify => self.toggle_notify(&arg, None).await,
+            // Сначала закрывается открытый чат
+            Action::Back if self.open_chat.is_some() => self.close_chat(&arg).await,
+            Action::Back | Action::Quit => return DialogListState::Closed,
+            _ => {}
+        }
+        DialogListState::List
+    }
+
+    async fn resolve_archive(&mut self, arg: ArgumentResolver) -> DialogListState {
+        systems::set_mode(&arg, &mut self.mode, Context::DialogList).await;
+        self.on_update(&arg).await;
+        if self.need_load {
+            self.load(&arg).await;
+        }
+        let action = match self.action(&arg).await {
+            Some(a) => a,
+            None => return DialogListState::Archive,
+        };
+        if self.archive_selected.navigate(&self.archive.ids(), action) {
+            return DialogListState::Archive;
+        }
+        let selected = self.archive_selected.selected;
+        match action {
+            Action::Archive | Action::Unarchive if self.archive.get(selected).is_some() => {
+                self.request(&arg, "unarchiving", move |c| async move {
+                    c.unarchive(selected).await?;
+                    Ok(Reply::Unarchived(selected))
+                })
+                .await;
+            }
+            Action::MuteHour | Action::MuteEightHours | Action::MuteForever | Action::Unmute
+                if self.archive.get(selected).is_some() =>
+            {
+                self.mute(&arg, action, selected).await;
+            }
+            Action::Back => {
+                // Разархивированные чаты и счётчик архива обновятся
+                self.load(&arg).await;
+                return DialogListState::List;
+            }
+            Action::Quit => return DialogListState::Closed,
+            _ => {}
+        }
+        DialogListState::Archive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyCode;
+
+    use tui::layout::Constraint;
+
+    use super::*;
+    use crate::ecs::testing::Harness;
+    use crate::ecs::{LayoutNode, LayoutTree};
+    use crate::systems::{login::Login, status::StatusBar};
+    use crate::tg::{fake::FAKE_CODE, FakeClient};
+
+    async fn harness(fake: Arc<FakeClient>) -> Harness {
+        let mut h = Harness::new(40, 10);
+        let client: Arc<dyn TgClient> = fake;
+        h.global.lock().await.insert(client);
+        h.global.lock().await.insert(Alerts::default());
+        h.global.lock().await.insert(Notifier::default());
+        let s = DialogList::system(3, h.global.clone(), true);
+        h.add_system(s);
+        h.set_root(3);
+        h
+    }
+
+    fn fake() -> Arc<FakeClient> {
+        let fake = FakeClient::new().authorized();
+        fake.add_chat(1, "Alice");
+        fake.add_chat(2, "Bob");
+        fake.add_archived(3, "Old project");
+        Arc::new(fake)
+    }
+
+    #[tokio::test]
+    async fn archive_row_opens_archive_and_unarchives() {
+        let fake = fake();
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        assert!(h.contains("A: Archived"), "{:?}", h.lines());
+        assert!(h.contains("Alice"));
+        assert!(!h.contains("Old project"));
+
+        // Строка архива первая и выбрана
+        h.key(KeyCode::Enter).await;
+        assert!(h.state_is(3, DialogListState::Archive));
+        h.remote().await;
+        assert!(h.contains("Old project"));
+
+        h.key(KeyCode::Char('a')).await;
+        h.remote().await;
+        assert!(!h.contains("Old project"));
+
+        h.key(KeyCode::Esc).await;
+        assert!(h.state_is(3, DialogListState::List));
+        h.remote().await;
+        assert!(h.contains("Old project"));
+        assert!(!h.contains("Archived"));
+    }
+
+    #[tokio::test]
+    async fn archive_action_moves_chat_to_archive() {
+        let fake = fake();
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        h.key(KeyCode::Down).await;
+        h.key(KeyCode::Down).await;
+        h.key(KeyCode::Char('a')).await;
+        h.remote().await;
+        assert!(!h.contains("Bob"), "{:?}", h.lines());
+        assert_eq!(fake.folder_dialogs(tg::ARCHIVE_FOLDER).await.unwrap().len(), 2);
+
+        assert!(!h.key(KeyCode::Char('q')).await);
+    }
+
+    #[tokio::test]
+    async fn muted_chat_is_marked_and_not_counted() {
+        let fake = fake();
+        fake.receive(1, "Alice", "hi");
+        fake.receive(2, "Bob", "hello");
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        assert!(h.contains("Dialogs(2)"), "{:?}", h.lines());
+
+        // Bob сверху после последнего сообщения
+        h.key(KeyCode::Down).await;
+        h.key(KeyCode::Char('m')).await;
+        h.remote().await;
+        assert!(h.contains("[m]Bob"), "{:?}", h.lines());
+        assert!(h.contains("Dialogs(1)"));
+        assert!(fake.dialogs().await.unwrap().iter().any(|d| d.id == 2 && d.muted));
+
+        h.key(KeyCode::Char('u')).await;
+        h.remote().await;
+        assert!(!h.contains("[m]"));
+        assert!(h.contains("Dialogs(2)"));
+    }
+
+    async fn alerts(h: &Harness) -> Alerts {
+        std::mem::take(h.global.lock().await.get_mut::<Alerts>().unwrap())
+    }
+
+    #[tokio::test]
+    async fn new_message_rings_unless_muted_or_disabled() {
+        let fake = fake();
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        assert_eq!(alerts(&h).await.title.as_deref(), Some("teleconsole"));
+
+        fake.receive(1, "Alice", "hi");
+        let m = fake.messages(1).pop().unwrap();
+        h.update(TgUpdate::NewMessage(m.clone())).await;
+        assert!(alerts(&h).await.bell);
+
+        // Уведомления выключены для выбранного чата: Alice
+        h.key(KeyCode::Down).await;
+        h.key(KeyCode::Char('n')).await;
+        h.update(TgUpdate::NewMessage(m.clone())).await;
+        assert!(!alerts(&h).await.bell);
+        h.key(KeyCode::Char('n')).await;
+
+        h.key(KeyCode::Char('m')).await;
+        h.remote().await;
+        h.update(TgUpdate::NewMessage(m)).await;
+        assert!(!alerts(&h).await.bell);
+    }
+
+    #[tokio::test]
+    async fn open_chat_is_not_notified() {
+        let fake = fake();
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        h.key(KeyCode::Down).await;
+        h.key(KeyCode::Enter).await;
+        assert!(h.bus.lock().await.deliveries().iter().any(|d| d.event.ends_with("ChatOpened") && d.to == 3));
+
+        fake.receive(1, "Alice", "hi");
+        h.update(TgUpdate::NewMessage(fake.messages(1).pop().unwrap())).await;
+        assert!(!alerts(&h).await.bell);
+        fake.receive(2, "Bob", "hi");
+        h.update(TgUpdate::NewMessage(fake.messages(2).pop().unwrap())).await;
+        assert!(alerts(&h).await.bell);
+
+        // Esc закрывает чат, список остаётся
+        assert!(h.key(KeyCode::Esc).await);
+        fake.receive(1, "Alice", "again");
+        h.update(TgUpdate::NewMessage(fake.messages(1).pop().unwrap())).await;
+        assert!(alerts(&h).await.bell);
+        assert!(!h.key(KeyCode::Esc).await);
+    }
+
+    #[tokio::test]
+    async fn drawn_with_theme_of_global() {
+        let fake = fake();
+        let mut h = harness(fake).await;
+        let theme = Theme::light();
+        h.global.lock().await.insert(theme);
+        h.tick().await;
+        h.remote().await;
+        // Рамка и выбранная строка архива
+        assert_eq!(h.buffer().get(0, 0).fg, theme.border.fg.unwrap());
+        assert_eq!(h.buffer().get(1, 1).style().patch(theme.selection), h.buffer().get(1, 1).style());
+        assert_ne!(theme.selection, Theme::dark().selection);
+    }
+
+    #[tokio::test]
+    async fn missed_updates_and_reconnect_update_list() {
+        let fake = fake();
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        let id = fake.send(2, "draft").await.unwrap().id;
+        h.update(TgUpdate::NewMessage(fake.messages(2).pop().unwrap())).await;
+        assert!(h.lines()[2].contains("Bob"), "{:?}", h.lines());
+
+        fake.disconnect();
+        fake.receive(1, "Alice", "while offline");
+        fake.edit(2, id, "fixed").await.unwrap();
+        fake.reconnect().await.unwrap();
+        let missed = fake.difference().await.unwrap();
+        assert_eq!(missed.len(), 2);
+        assert!(matches!(&missed[1], TgUpdate::MessageEdited(m) if m.text == "fixed"));
+        for u in missed.iter().cloned() {
+            h.update(u).await;
+        }
+        // Alice поднялась наверх с непрочитанным
+        assert!(h.lines()[2].contains("Alice(1)"), "{:?}", h.lines());
+
+        fake.receive(1, "Alice", "after");
+        h.bus.lock().await.publish(0, Reconnected { missed: missed.len() });
+        h.tick().await;
+        h.tick().await;
+        h.remote().await;
+        assert!(h.lines()[2].contains("Alice(2)"), "{:?}", h.lines());
+        assert!(h.contains("Dialogs(2)"));
+    }
+
+    #[tokio::test]
+    async fn archived_chat_is_updated_and_unknown_reloaded_once() {
+        let fake = fake();
+        fake.set_mute(3, MuteFor::Forever.until()).await.unwrap();
+        let mut h = harness(fake.clone()).await;
+        h.tick().await;
+        h.remote().await;
+        alerts(&h).await;
+
+        fake.receive(3, "Bob", "archived");
+        h.update(TgUpdate::NewMessage(fake.messages(3).pop().unwrap())).await;
+        assert!(h.contains("A: Archived (1 unread)"), "{:?}", h.lines());
+        assert!(!alerts(&h).await.bell);
+        assert!(h.scheduler.lock().await.next_deadline().is_none());
+
+        fake.add_chat(5, "Carol");
+        fake.receive(5, "Carol", "hi");
+        fake.receive(5, "Carol", "are you here?");
+        for m in fake.messages(5) {
+            h.update(TgUpdate::NewMessage(m)).await;
+        }
+        assert!(alerts(&h).await.bell);
+        assert!(!h.contains("Carol"));
+
+        h.wait(RELOAD_DELAY).await;
+        h.remote().await;
+        assert!(h.contains("Carol(2)"), "{:?}", h.lines());
+        assert!(h.scheduler.lock().await.next_deadline().is_none());
+    }
+
+    #[tokio::test]
+    async fn status_shows_mode_of_login_then_dialogs() {
+        let fake = Arc::new(FakeClient::new());
+        fake.add_chat(1, "Alice");
+        let mut h = Harness::new(40, 10);
+        let client: Arc<dyn TgClient> = fake.clone();
+        h.global.lock().await.insert(client);
+        h.add_system(Login::system(1, h.global.clone()));
+        h.add_system(StatusBar::system(2, h.global.clone()));
+        h.add_system(DialogList::system(3, h.global.clone(), false));
+        h.layout = LayoutTree::new(LayoutNode::vertical(vec![
+            (Constraint::Min(0), LayoutNode::System(3)),
+            (Constraint::Length(1), LayoutNode::System(2)),
+        ]))
+        .with_passive(2);
+        h.tick().await;
+        assert!(h.lines()[9].contains("popup"), "{:?}", h.lines());
+
+        h.type_text("+79990000000").await;
+        h.key(KeyCode::Enter).await;
+        h.remote().await;
+        h.tick().await;
+        h.type_text(FAKE_CODE).await;
+        h.key(KeyCode::Enter).await;
+        h.remote().await;
+        h.tick().await;
+        assert!(h.state_is(3, DialogListState::List));
+        assert!(h.lines()[9].contains("dialogs"), "{:?}", h.lines());
+        assert!(!h.lines()[9].contains("popup"));
+    }
+}
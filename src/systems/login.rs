@@ -1,16 +1,385 @@
-use crate::ecs::System;
-use crate::ecs::Key;
-use crate::ecs::Drawer;
+use std::{sync::Arc, time::Duration};
+
+use crossterm::event::{Event, KeyCode};
+use futures::Future;
+use tokio::sync::Mutex;
+use tui::{
+    layout::{Alignment, Rect},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+};
+
 use crate::ecs::ArgumentDrawer;
+use crate::ecs::ArgumentResolver;
+use crate::ecs::{system_struct, Resources, System, SystemId, SystemState};
+use crate::keymap::{Action, Context, Key, KeyResult, Keymap};
+use crate::systems::{self, AccountChanged, LoggedIn, Notice, OperationFinished, OperationStarted};
+use crate::text;
+use crate::theme::Theme;
+use crate::tg::{TgClient, TgErrors};
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, SystemState)]
 pub enum LoginState{
     PreLogin,
+    /// Code is requested, input waits for reply
+    SendingCode,
+    CodeInput,
+    SigningIn,
     EndLogin,
 }
 
+/// Reply of spawned request, stored in `Login::done` and resolver is woken to take it
+struct Done {
+    operation: &'static str,
+    reply: Result<(), TgErrors>,
+}
+
+#[derive(Default, System)]
+#[system(state = "LoginState", init = "PreLogin", end = "EndLogin")]
+#[drawer(PreLogin = "draw_phone", SendingCode = "draw_phone", CodeInput = "draw_code", SigningIn = "draw_code")]
+#[resolver(
+    PreLogin = "resolve_phone",
+    SendingCode = "resolve_sending",
+    CodeInput = "resolve_code",
+    SigningIn = "resolve_signing"
+)]
+pub struct Login {
+    pub phone: String,
+    pub code: String,
+    pub error: Option<String>,
+    keymap: Option<Keymap>,
+    /// Reply of request, error is already shown as `Notice`
+    done: Option<Result<(), String>>,
+    mode: Option<Context>,
+}
+
+fn center(window: Rect, w: u16, h: u16) -> Rect {
+    let w = w.min(window.width);
+    let h = h.min(window.height);
+    Rect {
+        width: w,
+        height: h,
+        x: window.x + (window.width - w) / 2,
+        y: window.y + (window.height - h) / 2,
+    }
+}
+
+enum Edit {
+    Typing,
+    Confirm,
+    Cancel,
+}
+
+/// Edit of input buffer by key of popup context, not bound chars are text
+fn edit(buf: &mut String, key: Option<KeyResult>, allowed: fn(char) -> bool) -> Edit {
+    match key {
+        Some(KeyResult::Action(Action::DeleteChar)) => {
+            text::pop_grapheme(buf);
+        }
+        Some(KeyResult::Action(Action::Confirm)) => return Edit::Confirm,
+        Some(KeyResult::Action(Action::Cancel)) => return Edit::Cancel,
+        Some(KeyResult::Unbound(Key {
+            code: KeyCode::Char(c),
+            modifiers,
+        })) if modifiers.is_empty() && allowed(c) => buf.push(c),
+        _ => {}
+    }
+    Edit::Typing
+}
+
+impl Login {
+    /// Subscribed to replies of its requests
+    pub fn system(id: SystemId, global: Arc<Mutex<Resources>>) -> System<SystemState> {
+        let mut s = System::from_struct(id, Login::default(), global);
+        s.on_event::<Done, _>(|system, done| {
+            Box::pin(async move {
+                let id = system.id();
+                {
+                    let mut bus = system.bus.lock().await;
+                    bus.publish(id, OperationFinished(done.operation));
+                    if let Err(e) = &done.reply {
+                        bus.publish(id, Notice::from(e));
+                    }
+                }
+                if let Ok(login) = system_struct::<Login>(&system.local).await {
+                    login.lock().await.done = Some(done.reply.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+                }
+                // Ответ пришёл после резолвера этого шага
+                system.scheduler.lock().await.once(id, Duration::ZERO);
+            })
+        });
+        s
+    }
+
+    async fn draw_input(&self, arg: &ArgumentDrawer<'_>, title: &str, value: &str) {
+        let theme = arg.global.lock().await.get::<Theme>().cloned().unwrap_or_default();
+        let mut f = arg.frame.lock().await;
+        let size = arg.area;
+        let title = match &self.error {
+            Some(e) => Spans::from(vec![Span::raw(format!("{}: ", title)), Span::styled(e.clone(), theme.error)]),
+            None => Spans::from(title.to_string()),
+        };
+        let area = center(size, 20, 3);
+        let block = Block::default().title(title).borders(Borders::ALL).border_style(theme.border);
+        let inner = block.inner(area);
+        // Длинный ввод прокручивается, видно конец и курсор за ним
+        let visible = text::tail_width(value, inner.width.saturating_sub(1) as usize);
+        let w = text::width(visible) as u16;
+        f.render_widget(Paragraph::new(visible).alignment(Alignment::Center).block(block), area);
+        if inner.width > 0 && inner.height > 0 {
+            let x = inner.x + (inner.width / 2).saturating_sub(w / 2) + w;
+            f.set_cursor(x.min(inner.right() - 1), inner.y);
+        }
+    }
+
+    async fn draw_phone(&self, arg: &ArgumentDrawer<'_>) {
+        self.draw_input(arg, "Phone input", &self.phone).await;
+    }
+
+    async fn draw_code(&self, arg: &ArgumentDrawer<'_>) {
+        self.draw_input(arg, "Code input", &self.code).await;
+    }
+
+    /// Key of popup context, keymap is copied from global on first key
+    async fn key(&mut self, arg: &ArgumentResolver) -> Option<KeyResult> {
+        let key = match arg.input {
+            Some(Event::Key(k)) => k,
+            _ => return None,
+        };
+        if self.keymap.is_none() {
+            self.keymap = Some(systems::keymap(arg).await);
+        }
+        Some(self.keymap.as_mut()?.feed(Context::Popup, key))
+    }
+
+    async fn resolve_phone(&mut self, arg: ArgumentResolver) -> LoginState {
+        systems::set_mode(&arg, &mut self.mode, Context::Popup).await;
+        let key = self.key(&arg).await;
+        match edit(&mut self.phone, key, |c| c.is_ascii_digit() || c == '+') {
+            Edit::Typing => return LoginState::PreLogin,
+            // Вход отменён, родитель решает что дальше
+            Edit::Cancel => {
+                self.mode = None;
+                return LoginState::EndLogin;
+            }
+            Edit::Confirm => {}
+        }
+        let client = match arg.resource::<Arc<dyn TgClient>>().await {
+            Ok(c) => c,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return LoginState::PreLogin;
+            }
+        };
+        let phone = self.phone.clone();
+        self.request(&arg, "login code", async move { client.request_login_code(&phone).await }).await;
+        LoginState::SendingCode
+    }
+
+    async fn resolve_sending(&mut self, _arg: ArgumentResolver) -> LoginState {
+        match self.done.take() {
+            None => LoginState::SendingCode,
+            Some(Ok(())) => {
+                self.error = None;
+                LoginState::CodeInput
+            }
+            Some(Err(e)) => {
+                self.error = Some(e);
+                LoginState::PreLogin
+            }
+        }
+    }
+
+    async fn resolve_code(&mut self, arg: ArgumentResolver) -> LoginState {
+        let key = self.key(&arg).await;
+        match edit(&mut self.code, key, |c| c.is_ascii_digit()) {
+            Edit::Typing => return LoginState::CodeInput,
+            // Назад к номеру, например если он введён с ошибкой
+            Edit::Cancel => {
+                self.code.clear();
+                self.error = None;
+                return LoginState::PreLogin;
+            }
+            Edit::Confirm => {}
+        }
+        let client = match arg.resource::<Arc<dyn TgClient>>().await {
+            Ok(c) => c,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return LoginState::PreLogin;
+            }
+        };
+        let code = std::mem::take(&mut self.code);
+        self.request(&arg, "sign in", async move { client.sign_in(&code).await }).await;
+        LoginState::SigningIn
+    }
+
+    async fn resolve_signing(&mut self, arg: ArgumentResolver) -> LoginState {
+        match self.done.take() {
+            None => LoginState::SigningIn,
+            Some(Ok(())) => {
+                self.error = None;
+                self.mode = None;
+                let mut bus = arg.bus.lock().await;
+                bus.publish(arg.id, LoggedIn);
+                let client = match arg.resource::<Arc<dyn TgClient>>().await {
+                    Ok(c) => c,
+                    Err(_) => return LoginState::EndLogin,
+                };
+                bus.publish(arg.id, OperationStarted("account"));
+                let tx = bus.sender(arg.id);
+                tokio::spawn(async move {
+                    match client.me().await {
+                        Ok(name) => tx.send(AccountChanged(name)),
+                        Err(e) => tx.send(Notice::from(&e)),
+                    };
+                    tx.send(OperationFinished("account"));
+                });
+                LoginState::EndLogin
+            }
+            Some(Err(e)) => {
+                self.error = Some(e);
+                LoginState::PreLogin
+            }
+        }
+    }
+
+    /// Request out of step, reply comes as `Done`
+    async fn request<F>(&self, arg: &ArgumentResolver, operation: &'static str, task: F)
+    where
+        F: Future<Output = Result<(), TgErrors>> + Send + 'static,
+    {
+        arg.bus.lock().await.publish(arg.id, OperationStarted(operation));
+        arg.spawn(async move {
+            Done {
+                operation,
+                reply: task.await,
+            }
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use tui::backend::Backend;
+
+    use super::*;
+    use crate::ecs::testing::Harness;
+    use crate::tg::{fake::FAKE_CODE, FakeClient};
+
+    fn harness() -> Harness {
+        let mut h = Harness::new(40, 10);
+        let s = Login::system(1, h.global.clone());
+        h.add_system(s);
+        h.set_root(1);
+        h
+    }
+
+    #[tokio::test]
+    async fn phone_accepts_only_digits_and_plus() {
+        let mut h = harness();
+        h.type_text("+7a9").await;
+        h.key(KeyCode::Backspace).await;
+        h.type_text("12").await;
+        assert!(h.contains("Phone input"));
+        assert!(h.contains("+712"));
+        assert!(h.state_is(1, LoginState::PreLogin));
+    }
 
-pub async fn DrawLogin<'a>(arg: ArgumentDrawer<'a>)->(){
-    let mut f = arg.frame.lock().await;
-    ()
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn cursor_follows_input() {
+        let mut h = harness();
+        h.type_text("+7").await;
+        // Поле 20x3 в центре 40x10, текст по центру внутри рамки
+        assert_eq!(h.terminal.backend_mut().get_cursor().unwrap(), (21, 4));
+    }
+
+    #[tokio::test]
+    async fn enter_without_client_shows_error() {
+        let mut h = harness();
+        h.type_text("+79").await;
+        h.key(KeyCode::Enter).await;
+        assert!(h.state_is(1, LoginState::PreLogin));
+        let login = system_struct::<Login>(&h.systems[&1].local).await.unwrap();
+        let error = login.lock().await.error.clone().unwrap();
+        assert!(error.contains("TgClient"), "{}", error);
+        assert!(h.bus.lock().await.deliveries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn login_with_fake_server() {
+        let mut h = harness();
+        let client: Arc<dyn TgClient> = Arc::new(FakeClient::new());
+        h.global.lock().await.insert(client.clone());
+        h.systems.get_mut(&1).unwrap().on_event::<LoggedIn, _>(|_, _| Box::pin(async {}));
+        h.type_text("+79990000000").await;
+        h.key(KeyCode::Enter).await;
+        // Ввод не ждёт сети
+        assert!(h.state_is(1, LoginState::SendingCode));
+        assert!(h.contains("Phone input"));
+        h.remote().await;
+        h.tick().await;
+        assert!(h.state_is(1, LoginState::CodeInput));
+        assert!(h.contains("Code input"));
+
+        h.type_text(FAKE_CODE).await;
+        assert!(h.key(KeyCode::Enter).await);
+        assert!(h.state_is(1, LoginState::SigningIn));
+        h.remote().await;
+        assert!(!h.tick().await);
+        assert!(client.is_authorized().await.unwrap());
+        let logged = h.bus.lock().await.deliveries().iter().filter(|d| d.event.ends_with("LoggedIn")).count();
+        assert_eq!(logged, 1);
+    }
+
+    #[tokio::test]
+    async fn wrong_code_is_error() {
+        let mut h = harness();
+        let client: Arc<dyn TgClient> = Arc::new(FakeClient::new());
+        h.global.lock().await.insert(client.clone());
+        h.type_text("+79990000000").await;
+        h.key(KeyCode::Enter).await;
+        h.remote().await;
+        h.tick().await;
+        h.type_text("1").await;
+        h.key(KeyCode::Enter).await;
+        h.remote().await;
+        h.tick().await;
+        assert!(h.state_is(1, LoginState::PreLogin));
+        let login = system_struct::<Login>(&h.systems[&1].local).await.unwrap();
+        assert!(login.lock().await.error.is_some());
+        assert!(!client.is_authorized().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn esc_goes_back_to_phone_and_cancels() {
+        let mut h = harness();
+        let client: Arc<dyn TgClient> = Arc::new(FakeClient::new());
+        h.global.lock().await.insert(client.clone());
+        h.type_text("+79990000000").await;
+        h.key(KeyCode::Enter).await;
+        h.remote().await;
+        h.tick().await;
+        h.type_text("12").await;
+        h.key(KeyCode::Esc).await;
+        assert!(h.state_is(1, LoginState::PreLogin));
+        assert!(h.contains("+79990000000"));
+
+        assert!(!h.key(KeyCode::Esc).await);
+        assert!(!client.is_authorized().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn keys_of_config_keymap() {
+        let mut h = harness();
+        h.global.lock().await.insert(Keymap::preset("emacs").unwrap());
+        h.type_text("+7").await;
+        // Символ с Ctrl не текст
+        h.input(Event::Key(KeyEvent::new(KeyCode::Char('h'), KeyModifiers::CONTROL))).await;
+        assert!(h.contains("+7"));
+        assert!(!h.contains("+7h"));
+        assert!(!h.input(Event::Key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL))).await);
+    }
+}
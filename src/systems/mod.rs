@@ -1 +1,113 @@
-pub mod login;
\ No newline at end of file
+use std::time::Duration;
+
+use crate::ecs::ArgumentResolver;
+use crate::keymap::{Context, Keymap};
+use crate::tg::TgErrors;
+
+pub mod dialog_list;
+pub mod login;
+pub mod status;
+
+/// Keymap from config, default preset when it is not in global
+pub async fn keymap(arg: &ArgumentResolver) -> Keymap {
+    match arg.resource::<Keymap>().await {
+        Ok(k) => k,
+        Err(_) => Keymap::preset("default").expect("default preset is valid"),
+    }
+}
+
+/// `ModeChanged` when mode differs from the last one published by system
+pub async fn set_mode(arg: &ArgumentResolver, last: &mut Option<Context>, mode: Context) {
+    if *last != Some(mode) {
+        *last = Some(mode);
+        arg.bus.lock().await.publish(arg.id, ModeChanged(mode));
+    }
+}
+
+// Events of `ecs::EventBus` shared between systems
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatOpened {
+    pub chat_id: i64,
+}
+
+/// Chat of last `ChatOpened` is not shown anymore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatClosed {
+    pub chat_id: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggedIn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Connection {
+    #[default]
+    Connecting,
+    Online,
+    /// Number of attempt
+    Reconnecting(u32),
+    Offline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionChanged(pub Connection);
+
+/// Updates stream is restored, missed updates were given to systems before it.
+/// Systems with dialog list and open chat reload them: difference can be too long to apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconnected {
+    pub missed: usize,
+}
+
+/// Name of logged in user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountChanged(pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeChanged(pub Context);
+
+/// Network work in background, shown in status bar until finished with the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationStarted(pub &'static str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationFinished(pub &'static str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoticeLevel {
+    Info,
+    Error,
+}
+
+/// Message in status bar which disappears after `ttl`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    pub level: NoticeLevel,
+    pub text: String,
+    pub ttl: Duration,
+}
+
+impl Notice {
+    pub fn info<T: ToString>(text: T) -> Self {
+        Notice {
+            level: NoticeLevel::Info,
+            text: text.to_string(),
+            ttl: Duration::from_secs(3),
+        }
+    }
+
+    pub fn error<T: ToString>(text: T) -> Self {
+        Notice {
+            level: NoticeLevel::Error,
+            text: text.to_string(),
+            ttl: Duration::from_secs(8),
+        }
+    }
+}
+
+impl From<&TgErrors> for Notice {
+    fn from(e: &TgErrors) -> Self {
+        Notice::error(crate::tg::report(e))
+    }
+}
@@ -0,0 +1,216 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use tokio::{sync::Mutex, time::Instant};
+use tui::{
+    style::Style,
+    text::{Span, Spans},
+    widgets::Paragraph,
+};
+
+use crate::ecs::{system_struct, ArgumentDrawer, ArgumentResolver, Resources, System, SystemId, SystemState};
+use crate::keymap::Context;
+use crate::systems::{
+    AccountChanged, Connection, ConnectionChanged, ModeChanged, Notice, NoticeLevel, OperationFinished, OperationStarted,
+};
+use crate::text;
+use crate::theme::Theme;
+
+/// Older notices are dropped even if not expired
+const MAX_NOTICES: usize = 8;
+
+#[derive(Debug, Clone, SystemState)]
+pub enum StatusState {
+    Shown,
+    Closed,
+}
+
+/// One line with connection, account, mode, pending operations and last notice.
+/// Filled only by events, so any system or spawned task can report to it.
+#[derive(Default, System)]
+#[system(state = "StatusState", init = "Shown", end = "Closed")]
+#[drawer(Shown = "draw")]
+#[resolver(Shown = "resolve")]
+pub struct StatusBar {
+    pub connection: Connection,
+    pub account: Option<String>,
+    pub mode: Option<Context>,
+    pub pending: Vec<&'static str>,
+    notices: VecDeque<(Notice, Instant)>,
+}
+
+impl StatusBar {
+    /// System subscribed to status events
+    pub fn system(id: SystemId, global: Arc<Mutex<Resources>>) -> System<SystemState> {
+        let mut s = System::from_struct(id, StatusBar::default(), global);
+        s.on_event::<ConnectionChanged, _>(|system, e| {
+            let c = e.0;
+            Box::pin(async move {
+                if let Ok(bar) = system_struct::<StatusBar>(&system.local).await {
+                    bar.lock().await.connection = c;
+                }
+            })
+        });
+        s.on_event::<AccountChanged, _>(|system, e| {
+            let name = e.0.clone();
+            Box::pin(async move {
+                if let Ok(bar) = system_struct::<StatusBar>(&system.local).await {
+                    bar.lock().await.account = Some(name);
+                }
+            })
+        });
+        s.on_event::<ModeChanged, _>(|system, e| {
+            let mode = e.0;
+            Box::pin(async move {
+                if let Ok(bar) = system_struct::<StatusBar>(&system.local).await {
+                    bar.lock().await.mode = Some(mode);
+                }
+            })
+        });
+        s.on_event::<OperationStarted, _>(|system, e| {
+            let name = e.0;
+            Box::pin(async move {
+                if let Ok(bar) = system_struct::<StatusBar>(&system.local).await {
+                    bar.lock().await.pending.push(name);
+                }
+            })
+        });
+        s.on_event::<OperationFinished, _>(|system, e| {
+            let name = e.0;
+            Box::pin(async move {
+                if let Ok(bar) = system_struct::<StatusBar>(&system.local).await {
+                    let mut bar = bar.lock().await;
+                    if let Some(i) = bar.pending.iter().position(|p| *p == name) {
+                        bar.pending.remove(i);
+                    }
+                }
+            })
+        });
+        s.on_event::<Notice, _>(|system, e| {
+            let n = e.clone();
+            Box::pin(async move {
+                // Пробуждение, чтобы сообщение исчезло без ввода
+                system.scheduler.lock().await.once(system.id(), n.ttl);
+                if let Ok(bar) = system_struct::<StatusBar>(&system.local).await {
+                    bar.lock().await.push(n);
+                }
+            })
+        });
+        s
+    }
+
+    pub fn push(&mut self, notice: Notice) {
+        let until = Instant::now() + notice.ttl;
+        self.notices.push_back((notice, until));
+        while self.notices.len() > MAX_NOTICES {
+            self.notices.pop_front();
+        }
+    }
+
+    /// Last not expired
+    pub fn notice(&self) -> Option<&Notice> {
+        let now = Instant::now();
+        self.notices.iter().rev().find(|(_, until)| *until > now).map(|(n, _)| n)
+    }
+
+    fn summary(&self) -> String {
+        let mut parts = vec![match self.connection {
+            Connection::Connecting => String::from("connecting…"),
+            Connection::Online => String::from("online"),
+            Connection::Reconnecting(1) => String::from("reconnecting…"),
+            Connection::Reconnecting(n) => format!("reconnecting… #{}", n),
+            Connection::Offline => String::from("offline"),
+        }];
+        if let Some(a) = &self.account {
+            parts.push(a.clone());
+        }
+        if let Some(m) = self.mode {
+            parts.push(m.name().to_string());
+        }
+        match self.pending.as_slice() {
+            [] => {}
+            [one] => parts.push(format!("{}…", one)),
+            [.., last] => parts.push(format!("{}… (+{})", last, self.pending.len() - 1)),
+        }
+        parts.join(" | ")
+    }
+
+    async fn draw(&self, arg: &ArgumentDrawer<'_>) {
+        let theme = arg.global.lock().await.get::<Theme>().cloned().unwrap_or_default();
+        let summary = self.summary();
+        let mut spans = vec![Span::styled(
+            summary.clone(),
+            if matches!(self.connection, Connection::Offline | Connection::Reconnecting(_)) {
+                theme.error
+            } else {
+                Style::default()
+            },
+        )];
+        if let Some(n) = self.notice() {
+            let room = (arg.area.width as usize).saturating_sub(text::width(&summary) + 3);
+            spans.push(Span::raw(" | "));
+            spans.push(Span::styled(
+                text::truncate(&n.text, room),
+                match n.level {
+                    NoticeLevel::Info => Style::default(),
+                    NoticeLevel::Error => theme.error,
+                },
+            ));
+        }
+        arg.frame.lock().await.render_widget(Paragraph::new(Spans::from(spans)), arg.area);
+    }
+
+    async fn resolve(&mut self, _arg: ArgumentResolver) -> StatusState {
+        let now = Instant::now();
+        self.notices.retain(|(_, until)| *until > now);
+        StatusState::Shown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::ecs::testing::Harness;
+    use crate::tg::TgErrors;
+
+    fn harness() -> Harness {
+        let mut h = Harness::new(60, 8);
+        let s = StatusBar::system(1, h.global.clone());
+        h.add_system(s);
+        h.set_root(1);
+        h
+    }
+
+    #[tokio::test]
+    async fn shows_state_from_events() {
+        let mut h = harness();
+        {
+            let mut bus = h.bus.lock().await;
+            bus.publish(0, ConnectionChanged(Connection::Online));
+            bus.publish(0, AccountChanged(String::from("Вася")));
+            bus.publish(0, ModeChanged(Context::ChatView));
+            bus.publish(0, OperationStarted("sending"));
+        }
+        h.tick().await;
+        assert_eq!(h.lines()[0].trim_end(), "online | Вася | chat | sending…");
+
+        h.bus.lock().await.publish(0, OperationFinished("sending"));
+        h.tick().await;
+        assert_eq!(h.lines()[0].trim_end(), "online | Вася | chat");
+    }
+
+    #[tokio::test]
+    async fn notice_expires() {
+        let mut h = harness();
+        let mut n = Notice::from(&TgErrors::UnknownChat(5));
+        n.ttl = Duration::from_millis(50);
+        h.bus.lock().await.publish(0, n);
+        h.tick().await;
+        assert!(h.contains("Chat 5 is unknown"), "{:?}", h.lines());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        h.tick().await;
+        assert!(!h.contains("Chat 5 is unknown"));
+    }
+}
@@ -0,0 +1,94 @@
+//! Text measured in terminal columns: graphemes and their display width, not bytes or chars.
+//! Cyrillic is one column per letter but two bytes, CJK and emoji are two columns.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub const ELLIPSIS: &str = "..";
+
+pub fn width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+/// Longest prefix of whole graphemes which fits `max` columns
+pub fn take_width(s: &str, max: usize) -> &str {
+    let mut w = 0;
+    for (i, g) in s.grapheme_indices(true) {
+        w += width(g);
+        if w > max {
+            return &s[..i];
+        }
+    }
+    s
+}
+
+/// Longest suffix of whole graphemes which fits `max` columns, end of input line is visible while typing
+pub fn tail_width(s: &str, max: usize) -> &str {
+    let mut w = 0;
+    for (i, g) in s.grapheme_indices(true).rev() {
+        w += width(g);
+        if w > max {
+            return &s[i + g.len()..];
+        }
+    }
+    s
+}
+
+/// Fits `max` columns, cut text ends with `ELLIPSIS`
+pub fn truncate(s: &str, max: usize) -> String {
+    if width(s) <= max {
+        return s.to_string();
+    }
+    match max.checked_sub(width(ELLIPSIS)) {
+        Some(w) => format!("{}{}", take_width(s, w), ELLIPSIS),
+        None => take_width(ELLIPSIS, max).to_string(),
+    }
+}
+
+/// Backspace in input: removes whole grapheme, emoji with modifiers too
+pub fn pop_grapheme(buf: &mut String) -> bool {
+    match buf.grapheme_indices(true).next_back() {
+        Some((i, _)) => {
+            buf.truncate(i);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_counts_columns() {
+        assert_eq!(width("Вася"), 4);
+        assert_eq!(width("日本"), 4);
+        assert_eq!(width("a👍"), 3);
+    }
+
+    #[test]
+    fn truncate_fits_columns() {
+        assert_eq!(truncate("Вася Пупкин", 8), "Вася П..");
+        assert_eq!(truncate("日本語チャット", 7), "日本..");
+        assert_eq!(truncate("short", 8), "short");
+        assert_eq!(truncate("abc", 1), ".");
+        // Флаг из двух символов не разрезается
+        assert_eq!(truncate("🇷🇺🇷🇺🇷🇺", 5), "🇷🇺..");
+    }
+
+    #[test]
+    fn tail_keeps_end() {
+        assert_eq!(tail_width("привет", 3), "вет");
+        assert_eq!(tail_width("日本語", 3), "語");
+    }
+
+    #[test]
+    fn pop_removes_grapheme() {
+        let mut s = String::from("ok👍🏽");
+        assert!(pop_grapheme(&mut s));
+        assert_eq!(s, "ok");
+        s.clear();
+        assert!(!pop_grapheme(&mut s));
+    }
+}
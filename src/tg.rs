@@ -1,14 +1,206 @@
-use grammers_client::{
-    client::updates::{AuthorizationError, InvocationError},
-    types::LoginToken,
-    Client, Config,
-};
+use std::{io, path::PathBuf};
+
+use grammers_client::client::updates::{AuthorizationError, InvocationError};
+use grammers_client::SignInError;
+use grammers_tl_types as tl;
 use thiserror::Error;
 
+use crate::config::ConfigError;
+use crate::ecs::EcsErrors;
+
+pub mod client;
+pub mod fake;
+pub mod request;
+pub mod supervisor;
+
+pub use self::client::{ChatId, ChatInfo, GrammersClient, TgClient, TgUpdate};
+pub use self::fake::FakeClient;
+pub use self::supervisor::{supervise, Backoff, Link};
+
+pub const MAIN_FOLDER: i32 = 0;
+pub const ARCHIVE_FOLDER: i32 = 1;
+
+/// Errors of the whole application: Telegram, session, config and terminal.
+/// Wrapped errors are kept as `source`, `report` prints the chain.
 #[derive(Debug, Error)]
 pub enum TgErrors {
-    #[error("Authorization error")]
-    Auth(#[from] AuthorizationError),
-    #[error("IncocationError")]
-    Invocation(#[from] InvocationError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Can`t open session file {path:?}")]
+    SessionIo {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Session is broken: {0}")]
+    Session(String),
+    #[error("Terminal error")]
+    Terminal(#[source] io::Error),
+    #[error("Interface error")]
+    Ui(#[source] EcsErrors),
+    #[error("Can`t connect to Telegram")]
+    Connect(#[source] AuthorizationError),
+    #[error("Connection lost")]
+    Network(#[source] io::Error),
+    #[error("Authorization failed")]
+    Auth(#[source] AuthorizationError),
+    #[error("Too many requests, wait {0} s")]
+    FloodWait(u32),
+    #[error("Slow mode in chat, wait {0} s")]
+    SlowMode(u32),
+    #[error("Telegram limited messages to strangers from this account")]
+    PeerFlood,
+    #[error("Telegram refused request: {name} ({code})")]
+    Rpc { code: i32, name: String },
+    #[error("Sign in failed")]
+    SignIn(#[source] SignInError),
+    #[error("Login code was not requested")]
+    NoLoginCode,
+    #[error("Chat {0} is unknown")]
+    UnknownChat(i64),
+    #[error("Message {0} not found")]
+    UnknownMessage(i32),
+    #[error("Message {0} has no media")]
+    NoMedia(i32),
+    #[error("File I/O failed")]
+    Io(#[from] io::Error),
+}
+
+impl TgErrors {
+    /// Exit code of `main`, different for every kind of failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TgErrors::Config(_) => 2,
+            TgErrors::SessionIo { .. } | TgErrors::Session(_) => 3,
+            TgErrors::Terminal(_) => 4,
+            TgErrors::Connect(_) | TgErrors::Network(_) => 5,
+            TgErrors::Auth(_) | TgErrors::SignIn(_) | TgErrors::NoLoginCode => 6,
+            TgErrors::FloodWait(_) | TgErrors::SlowMode(_) | TgErrors::PeerFlood | TgErrors::Rpc { .. } => 7,
+            TgErrors::Ui(_) => 8,
+            TgErrors::UnknownChat(_) | TgErrors::UnknownMessage(_) | TgErrors::NoMedia(_) | TgErrors::Io(_) => 1,
+        }
+    }
+}
+
+impl From<EcsErrors> for TgErrors {
+    fn from(e: EcsErrors) -> Self {
+        match e {
+            EcsErrors::Terminal(e) => TgErrors::Terminal(e),
+            e => TgErrors::Ui(e),
+        }
+    }
+}
+
+/// Message with all causes: `a: b: c`. Display of error does not include its source
+pub fn report(e: &(dyn std::error::Error + 'static)) -> String {
+    let mut out = e.to_string();
+    let mut source = e.source();
+    while let Some(s) = source {
+        out.push_str(": ");
+        out.push_str(&s.to_string());
+        source = s.source();
+    }
+    out
+}
+
+impl From<InvocationError> for TgErrors {
+    fn from(e: InvocationError) -> Self {
+        request::classify(e)
+    }
+}
+
+impl From<AuthorizationError> for TgErrors {
+    fn from(e: AuthorizationError) -> Self {
+        match e {
+            // Лимиты при входе такие же, как у остальных запросов
+            AuthorizationError::Invoke(e) => request::classify(e),
+            AuthorizationError::IO(e) => TgErrors::Network(e),
+            e => TgErrors::Auth(e),
+        }
+    }
+}
+
+impl From<SignInError> for TgErrors {
+    fn from(e: SignInError) -> Self {
+        match e {
+            SignInError::Other(e) => request::classify(e),
+            e => TgErrors::SignIn(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuteFor {
+    Hour,
+    EightHours,
+    Forever,
+}
+
+impl MuteFor {
+    pub fn until(&self) -> i32 {
+        let now = chrono::Utc::now().timestamp() as i32;
+        match self {
+            MuteFor::Hour => now + 60 * 60,
+            MuteFor::EightHours => now + 8 * 60 * 60,
+            MuteFor::Forever => i32::MAX,
+        }
+    }
+}
+
+/// Mute time in the past means chat is not muted
+pub fn mute_active(until: i32) -> bool {
+    until as i64 > chrono::Utc::now().timestamp()
+}
+
+pub fn is_muted(settings: &tl::enums::PeerNotifySettings) -> bool {
+    let tl::enums::PeerNotifySettings::Settings(s) = settings;
+    s.mute_until.is_some_and(mute_active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_shows_chain() {
+        let e = TgErrors::SessionIo {
+            path: PathBuf::from("/tmp/s.session"),
+            source: io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"),
+        };
+        assert_eq!(report(&e), "Can`t open session file \"/tmp/s.session\": permission denied");
+
+        let e = TgErrors::from(ConfigError::Invalid(String::from("fps must be positive")));
+        assert_eq!(report(&e), "Invalid config: fps must be positive");
+
+        let e = TgErrors::from(EcsErrors::Terminal(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe")));
+        assert_eq!(report(&e), "Terminal error: broken pipe");
+        let e = TgErrors::Ui(EcsErrors::NoSystem(3));
+        assert_eq!(report(&e), "Interface error: System 3 not found");
+        let e = TgErrors::SignIn(SignInError::InvalidCode);
+        assert!(report(&e).starts_with("Sign in failed: "));
+    }
+
+    #[test]
+    fn dropped_connection_is_network() {
+        assert!(matches!(request::classify(InvocationError::Dropped), TgErrors::Network(_)));
+        let e = AuthorizationError::IO(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(matches!(TgErrors::from(e), TgErrors::Network(_)));
+    }
+
+    #[test]
+    fn exit_codes_differ_by_kind() {
+        let codes = [
+            TgErrors::from(ConfigError::Invalid(String::new())).exit_code(),
+            TgErrors::Session(String::new()).exit_code(),
+            TgErrors::Terminal(io::Error::from(io::ErrorKind::Other)).exit_code(),
+            TgErrors::Network(io::Error::from(io::ErrorKind::ConnectionReset)).exit_code(),
+            TgErrors::NoLoginCode.exit_code(),
+            TgErrors::FloodWait(1).exit_code(),
+            TgErrors::from(EcsErrors::NoSystem(1)).exit_code(),
+        ];
+        for (i, c) in codes.iter().enumerate() {
+            assert!(*c > 1);
+            assert!(!codes[..i].contains(c), "{:?}", codes);
+        }
+    }
 }
@@ -0,0 +1,570 @@
+use std::{collections::HashMap, path::Path};
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use grammers_client::{
+    client::updates::AuthorizationError,
+    types::{Chat, LoginToken, Message, PackedChat, Update},
+    Client, Config, InitParams,
+};
+use grammers_session::Session;
+use grammers_tl_types as tl;
+use tokio::sync::Mutex;
+
+use super::request::{self, READ, WRITE};
+use super::{is_muted, TgErrors, ARCHIVE_FOLDER, MAIN_FOLDER};
+
+//TODO: Сделать постраничную загрузку, сейчас берём только первые FOLDER_LIMIT диалогов
+const FOLDER_LIMIT: i32 = 100;
+
+pub type ChatId = i64;
+pub type MessageId = i32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatInfo {
+    pub id: ChatId,
+    pub name: String,
+    pub unread: i32,
+    pub muted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageInfo {
+    pub id: MessageId,
+    pub chat: ChatId,
+    pub sender: Option<String>,
+    pub text: String,
+    pub outgoing: bool,
+    pub date: DateTime<Utc>,
+    /// Has media which can be downloaded
+    pub media: bool,
+}
+
+/// Updates which UI handles, in ECS they are given to resolvers through `UpdateCell`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TgUpdate {
+    NewMessage(MessageInfo),
+    MessageEdited(MessageInfo),
+    /// Chat is known only for channels
+    MessagesDeleted { chat: Option<ChatId>, ids: Vec<MessageId> },
+}
+
+impl TgUpdate {
+    /// Name without content, for logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TgUpdate::NewMessage(_) => "new message",
+            TgUpdate::MessageEdited(_) => "message edited",
+            TgUpdate::MessagesDeleted { .. } => "messages deleted",
+        }
+    }
+}
+
+/// Operations of Telegram which app uses: grammers client or fake server for tests and demo
+// История, отправка и файлы для окна чата, пока их вызывают только тесты
+#[allow(dead_code)]
+#[async_trait]
+pub trait TgClient: Send + Sync {
+    async fn is_authorized(&self) -> Result<bool, TgErrors>;
+    /// Code is sent to phone, pending login is kept by client
+    async fn request_login_code(&self, phone: &str) -> Result<(), TgErrors>;
+    async fn sign_in(&self, code: &str) -> Result<(), TgErrors>;
+    /// Name of logged in user
+    async fn me(&self) -> Result<String, TgErrors>;
+    async fn dialogs(&self) -> Result<Vec<ChatInfo>, TgErrors>;
+    /// Chats of folder, `dialogs` gives only main one
+    async fn folder_dialogs(&self, folder: i32) -> Result<Vec<ChatInfo>, TgErrors>;
+    async fn archive(&self, chat: ChatId) -> Result<(), TgErrors>;
+    async fn unarchive(&self, chat: ChatId) -> Result<(), TgErrors>;
+    /// Unix time, 0 for unmute
+    async fn set_mute(&self, chat: ChatId, until: i32) -> Result<(), TgErrors>;
+    /// Newest first, older than `offset_id` when it is not 0
+    async fn history(&self, chat: ChatId, offset_id: MessageId, limit: usize) -> Result<Vec<MessageInfo>, TgErrors>;
+    async fn send(&self, chat: ChatId, text: &str) -> Result<MessageInfo, TgErrors>;
+    async fn edit(&self, chat: ChatId, id: MessageId, text: &str) -> Result<(), TgErrors>;
+    async fn delete(&self, chat: ChatId, ids: &[MessageId]) -> Result<(), TgErrors>;
+    async fn download(&self, chat: ChatId, id: MessageId, path: &Path) -> Result<(), TgErrors>;
+    /// `None` when connection is closed
+    async fn next_update(&self) -> Result<Option<TgUpdate>, TgErrors>;
+    /// New connection with the same session after updates stream broke
+    async fn reconnect(&self) -> Result<(), TgErrors>;
+    /// Updates missed since last known state, `updates.getDifference`
+    async fn difference(&self) -> Result<Vec<TgUpdate>, TgErrors>;
+}
+
+pub struct GrammersClient {
+    client: Mutex<Client>,
+    /// Separate handle: `next_update` waits long and must not block requests
+    updates: Mutex<Client>,
+    api_id: i32,
+    api_hash: String,
+    token: Mutex<Option<LoginToken>>,
+    /// Chats seen in dialogs and updates, requests need access hash
+    chats: Mutex<HashMap<ChatId, PackedChat>>,
+    /// Chats of folders, loaded raw without `PackedChat`
+    peers: Mutex<HashMap<ChatId, tl::enums::InputPeer>>,
+    /// Start point of `difference`, taken after login and after each difference
+    state: Mutex<Option<tl::types::updates::State>>,
+    /// Newest message of chat received by `next_update`, difference repeats them since state is not moved by updates
+    last_seen: Mutex<HashMap<ChatId, MessageId>>,
+}
+
+fn peer_id(peer: &tl::enums::Peer) -> ChatId {
+    match peer {
+        tl::enums::Peer::User(u) => u.user_id,
+        tl::enums::Peer::Chat(c) => c.chat_id,
+        tl::enums::Peer::Channel(c) => c.channel_id,
+    }
+}
+
+fn user_name(u: &tl::types::User) -> String {
+    match (&u.first_name, &u.last_name) {
+        (Some(f), Some(l)) => format!("{} {}", f, l),
+        (Some(f), None) => f.clone(),
+        (None, Some(l)) => l.clone(),
+        (None, None) => String::from("Deleted account"),
+    }
+}
+
+type Peers = HashMap<ChatId, (String, tl::enums::InputPeer)>;
+
+/// Names and input peers of users and chats which come with raw responses
+fn peers(users: &[tl::enums::User], chats: &[tl::enums::Chat]) -> Peers {
+    let mut peers = HashMap::new();
+    for u in users {
+        if let tl::enums::User::User(u) = u {
+            let peer = tl::types::InputPeerUser {
+                user_id: u.id,
+                access_hash: u.access_hash.unwrap_or(0),
+            };
+            peers.insert(u.id, (user_name(u), peer.into()));
+        }
+    }
+    for c in chats {
+        match c {
+            tl::enums::Chat::Chat(c) => {
+                let peer = tl::types::InputPeerChat { chat_id: c.id };
+                peers.insert(c.id, (c.title.clone(), peer.into()));
+            }
+            tl::enums::Chat::Channel(c) => {
+                let peer = tl::types::InputPeerChannel {
+                    channel_id: c.id,
+                    access_hash: c.access_hash.unwrap_or(0),
+                };
+                peers.insert(c.id, (c.title.clone(), peer.into()));
+            }
+            _ => {}
+        }
+    }
+    peers
+}
+
+/// Messages of difference come raw, without `types::Message` wrapper
+fn raw_message_info(m: &tl::enums::Message, peers: &Peers) -> Option<MessageInfo> {
+    let m = match m {
+        tl::enums::Message::Message(m) => m,
+        _ => return None,
+    };
+    let sender = m.from_id.as_ref().unwrap_or(&m.peer_id);
+    Some(MessageInfo {
+        id: m.id,
+        chat: peer_id(&m.peer_id),
+        sender: peers.get(&peer_id(sender)).map(|(name, _)| name.clone()),
+        text: m.message.clone(),
+        outgoing: m.out,
+        date: Utc.timestamp_opt(m.date as i64, 0).single().unwrap_or_else(Utc::now),
+        media: m.media.is_some(),
+    })
+}
+
+/// Edits and deletes of difference, other updates UI does not need
+fn raw_update(u: &tl::enums::Update, peers: &Peers) -> Option<TgUpdate> {
+    match u {
+        tl::enums::Update::EditMessage(e) => raw_message_info(&e.message, peers).map(TgUpdate::MessageEdited),
+        tl::enums::Update::EditChannelMessage(e) => raw_message_info(&e.message, peers).map(TgUpdate::MessageEdited),
+        tl::enums::Update::DeleteMessages(d) => Some(TgUpdate::MessagesDeleted {
+            chat: None,
+            ids: d.messages.clone(),
+        }),
+        tl::enums::Update::DeleteChannelMessages(d) => Some(TgUpdate::MessagesDeleted {
+            chat: Some(d.channel_id),
+            ids: d.messages.clone(),
+        }),
+        _ => None,
+    }
+}
+
+impl GrammersClient {
+    pub async fn connect(config: Config) -> Result<Self, TgErrors> {
+        let api_id = config.api_id;
+        let api_hash = config.api_hash.clone();
+        let client = Client::connect(config).await.map_err(TgErrors::Connect)?;
+        let client = GrammersClient {
+            updates: Mutex::new(client.clone()),
+            client: Mutex::new(client),
+            api_id,
+            api_hash,
+            token: Mutex::new(None),
+            chats: Mutex::new(HashMap::new()),
+            peers: Mutex::new(HashMap::new()),
+            state: Mutex::new(None),
+            last_seen: Mutex::new(HashMap::new()),
+        };
+        // До входа состояния нет, оно берётся после sign_in
+        client.save_state().await;
+        Ok(client)
+    }
+
+    /// Without state `difference` is empty, so error is not fatal
+    async fn save_state(&self) {
+        let client = self.client.lock().await.clone();
+        let client = &client;
+        let state = request::call(READ, || async move { Ok(client.invoke(&tl::functions::updates::GetState {}).await?) }).await;
+        if let Ok(tl::enums::updates::State::State(s)) = state {
+            *self.state.lock().await = Some(s);
+        }
+    }
+
+    async fn remember(&self, chat: &Chat) {
+        self.chats.lock().await.insert(chat.id(), chat.pack());
+    }
+
+    async fn packed(&self, chat: ChatId) -> Result<PackedChat, TgErrors> {
+        self.chats.lock().await.get(&chat).cloned().ok_or(TgErrors::UnknownChat(chat))
+    }
+
+    /// Chat of main list or of folder
+    async fn input_peer(&self, chat: ChatId) -> Result<tl::enums::InputPeer, TgErrors> {
+        if let Some(p) = self.chats.lock().await.get(&chat) {
+            return Ok(p.to_input_peer());
+        }
+        self.peers.lock().await.get(&chat).cloned().ok_or(TgErrors::UnknownChat(chat))
+    }
+
+    async fn message_info(&self, m: &Message) -> MessageInfo {
+        let chat = m.chat();
+        self.remember(&chat).await;
+        MessageInfo {
+            id: m.id(),
+            chat: chat.id(),
+            sender: m.sender().map(|s| s.name().to_string()),
+            text: m.text().to_string(),
+            outgoing: m.outgoing(),
+            date: m.date(),
+            media: m.media().is_some(),
+        }
+    }
+
+    async fn load_dialogs(&self) -> Result<Vec<ChatInfo>, TgErrors> {
+        let client = self.client.lock().await;
+        let mut iter = client.iter_dialogs();
+        let mut out = Vec::new();
+        while let Some(d) = iter.next().await? {
+            // Папка архива не чат, её чаты берутся через folder_dialogs
+            let dd = match &d.dialog {
+                tl::enums::Dialog::Dialog(dd) => dd,
+                tl::enums::Dialog::Folder(_) => continue,
+            };
+            let chat = d.chat();
+            self.remember(chat).await;
+            out.push(ChatInfo {
+                id: chat.id(),
+                name: chat.name().to_string(),
+                unread: dd.unread_count,
+                muted: is_muted(&dd.notify_settings),
+            });
+        }
+        Ok(out)
+    }
+
+    async fn load_folder(&self, folder: i32) -> Result<Vec<ChatInfo>, TgErrors> {
+        let r = self
+            .client
+            .lock()
+            .await
+            .invoke(&tl::functions::messages::GetDialogs {
+                exclude_pinned: false,
+                folder_id: Some(folder),
+                offset_date: 0,
+                offset_id: 0,
+                offset_peer: tl::enums::InputPeer::Empty,
+                limit: FOLDER_LIMIT,
+                hash: 0,
+            })
+            .await?;
+        let (dialogs, users, chats) = match r {
+            tl::enums::messages::Dialogs::Dialogs(d) => (d.dialogs, d.users, d.chats),
+            tl::enums::messages::Dialogs::Slice(d) => (d.dialogs, d.users, d.chats),
+            tl::enums::messages::Dialogs::NotModified(_) => return Ok(Vec::new()),
+        };
+        let peers = peers(&users, &chats);
+        let mut known = self.peers.lock().await;
+        Ok(dialogs
+            .into_iter()
+            .filter_map(|d| match d {
+                tl::enums::Dialog::Dialog(d) => {
+                    let id = peer_id(&d.peer);
+                    let (name, peer) = peers.get(&id)?.clone();
+                    known.insert(id, peer);
+                    Some(ChatInfo {
+                        id,
+                        name,
+                        unread: d.unread_count,
+                        muted: is_muted(&d.notify_settings),
+                    })
+                }
+                tl::enums::Dialog::Folder(_) => None,
+            })
+            .collect())
+    }
+
+    async fn set_folder(&self, chat: ChatId, folder: i32) -> Result<(), TgErrors> {
+        let peer = self.input_peer(chat).await?;
+        let req = tl::functions::folders::EditPeerFolders {
+            folder_peers: vec![tl::types::InputFolderPeer { peer, folder_id: folder }.into()],
+        };
+        let req = &req;
+        request::call(WRITE, || async move {
+            self.client.lock().await.invoke(req).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_history(&self, packed: PackedChat, offset_id: MessageId, limit: usize) -> Result<Vec<MessageInfo>, TgErrors> {
+        let client = self.client.lock().await;
+        let mut iter = client.iter_messages(packed).limit(limit);
+        if offset_id != 0 {
+            iter = iter.offset_id(offset_id);
+        }
+        let mut out = Vec::new();
+        while let Some(m) = iter.next().await? {
+            out.push(self.message_info(&m).await);
+        }
+        Ok(out)
+    }
+
+    async fn load_media(&self, packed: PackedChat, id: MessageId, path: &Path) -> Result<(), TgErrors> {
+        let client = self.client.lock().await;
+        let m = client
+            .get_messages_by_id(packed, &[id])
+            .await?
+            .pop()
+            .flatten()
+            .ok_or(TgErrors::UnknownMessage(id))?;
+        let media = m.media().ok_or(TgErrors::NoMedia(id))?;
+        client.download_media(&media, path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TgClient for GrammersClient {
+    async fn is_authorized(&self) -> Result<bool, TgErrors> {
+        request::call(READ, || async move { Ok(self.client.lock().await.is_authorized().await?) }).await
+    }
+
+    async fn request_login_code(&self, phone: &str) -> Result<(), TgErrors> {
+        let token = request::call(WRITE, || async move {
+            Ok(self
+                .client
+                .lock()
+                .await
+                .request_login_code(phone, self.api_id, &self.api_hash)
+                .await?)
+        })
+        .await?;
+        *self.token.lock().await = Some(token);
+        Ok(())
+    }
+
+    async fn sign_in(&self, code: &str) -> Result<(), TgErrors> {
+        let token = self.token.lock().await.take().ok_or(TgErrors::NoLoginCode)?;
+        let token = &token;
+        request::call(WRITE, || async move {
+            self.client.lock().await.sign_in(token, code).await?;
+            Ok(())
+        })
+        .await?;
+        self.save_state().await;
+        Ok(())
+    }
+
+    async fn me(&self) -> Result<String, TgErrors> {
+        request::call(READ, || async move { Ok(self.client.lock().await.get_me().await?.full_name()) }).await
+    }
+
+    async fn dialogs(&self) -> Result<Vec<ChatInfo>, TgErrors> {
+        request::call(READ, || self.load_dialogs()).await
+    }
+
+    async fn folder_dialogs(&self, folder: i32) -> Result<Vec<ChatInfo>, TgErrors> {
+        request::call(READ, || self.load_folder(folder)).await
+    }
+
+    async fn archive(&self, chat: ChatId) -> Result<(), TgErrors> {
+        self.set_folder(chat, ARCHIVE_FOLDER).await
+    }
+
+    async fn unarchive(&self, chat: ChatId) -> Result<(), TgErrors> {
+        self.set_folder(chat, MAIN_FOLDER).await
+    }
+
+    async fn set_mute(&self, chat: ChatId, until: i32) -> Result<(), TgErrors> {
+        let peer = self.input_peer(chat).await?;
+        let req = tl::functions::account::UpdateNotifySettings {
+            peer: tl::types::InputNotifyPeer { peer }.into(),
+            settings: tl::types::InputPeerNotifySettings {
+                show_previews: None,
+                silent: None,
+                mute_until: Some(until),
+                sound: None,
+            }
+            .into(),
+        };
+        let req = &req;
+        request::call(WRITE, || async move {
+            self.client.lock().await.invoke(req).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn history(&self, chat: ChatId, offset_id: MessageId, limit: usize) -> Result<Vec<MessageInfo>, TgErrors> {
+        let packed = self.packed(chat).await?;
+        request::call(READ, || self.load_history(packed, offset_id, limit)).await
+    }
+
+    async fn send(&self, chat: ChatId, text: &str) -> Result<MessageInfo, TgErrors> {
+        let packed = self.packed(chat).await?;
+        let m = request::call(WRITE, || async move { Ok(self.client.lock().await.send_message(packed, text).await?) }).await?;
+        Ok(self.message_info(&m).await)
+    }
+
+    async fn edit(&self, chat: ChatId, id: MessageId, text: &str) -> Result<(), TgErrors> {
+        let packed = self.packed(chat).await?;
+        request::call(WRITE, || async move { Ok(self.client.lock().await.edit_message(packed, id, text).await?) }).await
+    }
+
+    async fn delete(&self, chat: ChatId, ids: &[MessageId]) -> Result<(), TgErrors> {
+        let packed = self.packed(chat).await?;
+        request::call(WRITE, || async move {
+            self.client.lock().await.delete_messages(packed, ids).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn download(&self, chat: ChatId, id: MessageId, path: &Path) -> Result<(), TgErrors> {
+        let packed = self.packed(chat).await?;
+        request::call(READ, || self.load_media(packed, id, path)).await
+    }
+
+    async fn next_update(&self) -> Result<Option<TgUpdate>, TgErrors> {
+        loop {
+            let u = match self.updates.lock().await.next_update().await? {
+                Some(u) => u,
+                None => return Ok(None),
+            };
+            // Остальные обновления UI пока не нужны
+            return Ok(Some(match u {
+                Update::NewMessage(m) => {
+                    let info = self.message_info(&m).await;
+                    let mut seen = self.last_seen.lock().await;
+                    let last = seen.entry(info.chat).or_insert(info.id);
+                    *last = (*last).max(info.id);
+                    TgUpdate::NewMessage(info)
+                }
+                Update::MessageEdited(m) => TgUpdate::MessageEdited(self.message_info(&m).await),
+                Update::MessageDeleted(d) => TgUpdate::MessagesDeleted {
+                    chat: d.channel_id(),
+                    ids: d.messages().to_vec(),
+                },
+                _ => continue,
+            }));
+        }
+    }
+
+    async fn reconnect(&self) -> Result<(), TgErrors> {
+        let data = self.client.lock().await.session().save();
+        let data = &data;
+        let client = request::call(READ, || async move {
+            let session = Session::load(data).map_err(|e| TgErrors::Session(e.to_string()))?;
+            let config = Config {
+                session,
+                api_id: self.api_id,
+                api_hash: self.api_hash.clone(),
+                params: InitParams::default(),
+            };
+            Client::connect(config).await.map_err(|e| match e {
+                // Лимит ждём как у остальных запросов
+                AuthorizationError::Invoke(e) => request::classify(e),
+                e => TgErrors::Connect(e),
+            })
+        })
+        .await?;
+        *self.updates.lock().await = client.clone();
+        *self.client.lock().await = client;
+        Ok(())
+    }
+
+    async fn difference(&self) -> Result<Vec<TgUpdate>, TgErrors> {
+        let mut state = match self.state.lock().await.clone() {
+            Some(s) => s,
+            None => {
+                self.save_state().await;
+                return Ok(Vec::new());
+            }
+        };
+        let client = self.client.lock().await.clone();
+        let mut out = Vec::new();
+        loop {
+            let req = tl::functions::updates::GetDifference {
+                pts: state.pts,
+                pts_total_limit: None,
+                date: state.date,
+                qts: state.qts,
+            };
+            let (client, req) = (&client, &req);
+            let diff = request::call(READ, || async move { Ok(client.invoke(req).await?) }).await?;
+            // Slice: сервер отдал часть, продолжаем с промежуточного состояния
+            let (messages, other, users, chats, next, done) = match diff {
+                tl::enums::updates::Difference::Empty(e) => {
+                    state.date = e.date;
+                    state.seq = e.seq;
+                    break;
+                }
+                tl::enums::updates::Difference::TooLong(d) => {
+                    // Пропущено слишком много, UI перезагружает диалоги по событию переподключения
+                    state.pts = d.pts;
+                    break;
+                }
+                tl::enums::updates::Difference::Difference(d) => {
+                    let tl::enums::updates::State::State(s) = d.state;
+                    (d.new_messages, d.other_updates, d.users, d.chats, s, true)
+                }
+                tl::enums::updates::Difference::Slice(d) => {
+                    let tl::enums::updates::State::State(s) = d.intermediate_state;
+                    (d.new_messages, d.other_updates, d.users, d.chats, s, false)
+                }
+            };
+            let peers = peers(&users, &chats);
+            let seen = self.last_seen.lock().await;
+            out.extend(
+                messages
+                    .iter()
+                    .filter_map(|m| raw_message_info(m, &peers))
+                    .filter(|m| seen.get(&m.chat).is_none_or(|last| m.id > *last))
+                    .map(TgUpdate::NewMessage),
+            );
+            // Правки и удаления после новых сообщений: правка может быть у сообщения из этой же разницы
+            out.extend(other.iter().filter_map(|u| raw_update(u, &peers)));
+            state = next;
+            if done {
+                break;
+            }
+        }
+        *self.state.lock().await = Some(state);
+        Ok(out)
+    }
+}
@@ -0,0 +1,413 @@
+use std::{collections::HashMap, fs, io, path::Path, sync::Mutex as StdMutex};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use grammers_client::SignInError;
+use tokio::sync::{mpsc, Mutex};
+
+use super::client::{ChatId, ChatInfo, MessageId, MessageInfo, TgClient, TgUpdate};
+use super::{mute_active, TgErrors, ARCHIVE_FOLDER, MAIN_FOLDER};
+
+/// Code which fake server accepts for any phone
+pub const FAKE_CODE: &str = "12345";
+
+#[derive(Default)]
+struct Server {
+    authorized: bool,
+    phone: Option<String>,
+    /// In order of dialogs list
+    chats: Vec<ChatInfo>,
+    archived: Vec<ChatInfo>,
+    /// Oldest first
+    messages: HashMap<ChatId, Vec<MessageInfo>>,
+    next_id: MessageId,
+    offline: bool,
+    /// Updates while offline, given by `difference`
+    missed: Vec<TgUpdate>,
+    failed_reconnects: u32,
+}
+
+impl Server {
+    fn chat(&mut self, id: ChatId) -> Result<&mut ChatInfo, TgErrors> {
+        self.chats
+            .iter_mut()
+            .chain(self.archived.iter_mut())
+            .find(|c| c.id == id)
+            .ok_or(TgErrors::UnknownChat(id))
+    }
+
+    /// Chat goes to top of other list
+    fn move_chat(&mut self, id: ChatId, archive: bool) -> Result<(), TgErrors> {
+        let (from, to) = if archive {
+            (&mut self.chats, &mut self.archived)
+        } else {
+            (&mut self.archived, &mut self.chats)
+        };
+        if to.iter().any(|c| c.id == id) {
+            return Ok(());
+        }
+        let pos = from.iter().position(|c| c.id == id).ok_or(TgErrors::UnknownChat(id))?;
+        to.insert(0, from.remove(pos));
+        Ok(())
+    }
+
+    fn message(&mut self, chat: ChatId, id: MessageId) -> Result<&mut MessageInfo, TgErrors> {
+        self.messages
+            .get_mut(&chat)
+            .and_then(|l| l.iter_mut().find(|m| m.id == id))
+            .ok_or(TgErrors::UnknownMessage(id))
+    }
+
+    fn push(&mut self, chat: ChatId, sender: Option<String>, text: &str, outgoing: bool, media: bool) -> Result<MessageInfo, TgErrors> {
+        self.next_id += 1;
+        let m = MessageInfo {
+            id: self.next_id,
+            chat,
+            sender,
+            text: text.to_string(),
+            outgoing,
+            date: Utc::now(),
+            media,
+        };
+        let list = if self.archived.iter().any(|c| c.id == chat) {
+            &mut self.archived
+        } else {
+            &mut self.chats
+        };
+        let pos = list.iter().position(|c| c.id == chat).ok_or(TgErrors::UnknownChat(chat))?;
+        // Диалог с новым сообщением поднимается наверх
+        let mut c = list.remove(pos);
+        if !outgoing {
+            c.unread += 1;
+        }
+        list.insert(0, c);
+        self.messages.entry(chat).or_default().push(m.clone());
+        Ok(m)
+    }
+}
+
+/// In-memory Telegram: users, chats and messages, emits updates like real server.
+/// Used by tests and `--demo`.
+pub struct FakeClient {
+    server: StdMutex<Server>,
+    /// `None` breaks updates stream
+    tx: mpsc::UnboundedSender<Option<TgUpdate>>,
+    rx: Mutex<mpsc::UnboundedReceiver<Option<TgUpdate>>>,
+}
+
+impl Default for FakeClient {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        FakeClient {
+            server: StdMutex::new(Server::default()),
+            tx,
+            rx: Mutex::new(rx),
+        }
+    }
+}
+
+impl FakeClient {
+    pub fn new() -> Self {
+        FakeClient::default()
+    }
+
+    /// Logged in account with a few chats
+    pub fn demo() -> Self {
+        let fake = FakeClient::new().authorized();
+        fake.add_chat(1, "Saved Messages");
+        fake.add_chat(2, "Alice");
+        fake.add_chat(3, "Rust chat");
+        fake.add_archived(4, "Old project");
+        fake.receive(2, "Alice", "Hi! This is demo of teleconsole");
+        fake.receive(3, "Bob", "Anyone tried tui 0.17?");
+        fake.receive_media(3, "Bob", "screenshot.png");
+        fake
+    }
+
+    pub fn authorized(self) -> Self {
+        self.server.lock().unwrap().authorized = true;
+        self
+    }
+
+    pub fn add_chat(&self, id: ChatId, name: &str) {
+        self.server.lock().unwrap().chats.push(ChatInfo {
+            id,
+            name: name.to_string(),
+            unread: 0,
+            muted: false,
+        });
+    }
+
+    pub fn add_archived(&self, id: ChatId, name: &str) {
+        self.server.lock().unwrap().archived.push(ChatInfo {
+            id,
+            name: name.to_string(),
+            unread: 0,
+            muted: false,
+        });
+    }
+
+    /// Incoming message from other user, emits `NewMessage`
+    pub fn receive(&self, chat: ChatId, sender: &str, text: &str) -> MessageId {
+        self.incoming(chat, sender, text, false)
+    }
+
+    pub fn receive_media(&self, chat: ChatId, sender: &str, caption: &str) -> MessageId {
+        self.incoming(chat, sender, caption, true)
+    }
+
+    fn incoming(&self, chat: ChatId, sender: &str, text: &str, media: bool) -> MessageId {
+        let m = self
+            .server
+            .lock()
+            .unwrap()
+            .push(chat, Some(sender.to_string()), text, false, media)
+            .expect("fake chat must be added before messages");
+        let id = m.id;
+        self.emit(TgUpdate::NewMessage(m));
+        id
+    }
+
+    pub fn emit(&self, update: TgUpdate) {
+        let mut s = self.server.lock().unwrap();
+        if s.offline {
+            s.missed.push(update);
+        } else {
+            // Получатель живёт столько же сколько клиент
+            let _ = self.tx.send(Some(update));
+        }
+    }
+
+    /// Connection is lost: `next_update` fails, updates are kept for `difference`
+    #[cfg(test)]
+    pub fn disconnect(&self) {
+        self.server.lock().unwrap().offline = true;
+        let _ = self.tx.send(None);
+    }
+
+    /// Next `count` reconnects fail
+    #[cfg(test)]
+    pub fn fail_reconnects(&self, count: u32) {
+        self.server.lock().unwrap().failed_reconnects = count;
+    }
+
+    #[cfg(test)]
+    pub fn messages(&self, chat: ChatId) -> Vec<MessageInfo> {
+        self.server.lock().unwrap().messages.get(&chat).cloned().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl TgClient for FakeClient {
+    async fn is_authorized(&self) -> Result<bool, TgErrors> {
+        Ok(self.server.lock().unwrap().authorized)
+    }
+
+    async fn request_login_code(&self, phone: &str) -> Result<(), TgErrors> {
+        if phone.trim_start_matches('+').is_empty() {
+            return Err(TgErrors::Rpc {
+                code: 400,
+                name: String::from("PHONE_NUMBER_INVALID"),
+            });
+        }
+        self.server.lock().unwrap().phone = Some(phone.to_string());
+        Ok(())
+    }
+
+    async fn sign_in(&self, code: &str) -> Result<(), TgErrors> {
+        let mut s = self.server.lock().unwrap();
+        if s.phone.is_none() {
+            return Err(TgErrors::NoLoginCode);
+        }
+        if code != FAKE_CODE {
+            return Err(TgErrors::SignIn(SignInError::InvalidCode));
+        }
+        s.authorized = true;
+        Ok(())
+    }
+
+    async fn me(&self) -> Result<String, TgErrors> {
+        let s = self.server.lock().unwrap();
+        if !s.authorized {
+            return Err(TgErrors::Rpc {
+                code: 401,
+                name: String::from("AUTH_KEY_UNREGISTERED"),
+            });
+        }
+        Ok(s.phone.clone().unwrap_or_else(|| String::from("Demo")))
+    }
+
+    async fn dialogs(&self) -> Result<Vec<ChatInfo>, TgErrors> {
+        Ok(self.server.lock().unwrap().chats.clone())
+    }
+
+    async fn folder_dialogs(&self, folder: i32) -> Result<Vec<ChatInfo>, TgErrors> {
+        let s = self.server.lock().unwrap();
+        Ok(match folder {
+            MAIN_FOLDER => s.chats.clone(),
+            ARCHIVE_FOLDER => s.archived.clone(),
+            _ => Vec::new(),
+        })
+    }
+
+    async fn archive(&self, chat: ChatId) -> Result<(), TgErrors> {
+        self.server.lock().unwrap().move_chat(chat, true)
+    }
+
+    async fn unarchive(&self, chat: ChatId) -> Result<(), TgErrors> {
+        self.server.lock().unwrap().move_chat(chat, false)
+    }
+
+    async fn set_mute(&self, chat: ChatId, until: i32) -> Result<(), TgErrors> {
+        self.server.lock().unwrap().chat(chat)?.muted = mute_active(until);
+        Ok(())
+    }
+
+    async fn history(&self, chat: ChatId, offset_id: MessageId, limit: usize) -> Result<Vec<MessageInfo>, TgErrors> {
+        let mut s = self.server.lock().unwrap();
+        s.chat(chat)?;
+        let all = s.messages.get(&chat).cloned().unwrap_or_default();
+        Ok(all
+            .into_iter()
+            .rev()
+            .filter(|m| offset_id == 0 || m.id < offset_id)
+            .take(limit)
+            .collect())
+    }
+
+    async fn send(&self, chat: ChatId, text: &str) -> Result<MessageInfo, TgErrors> {
+        self.server.lock().unwrap().push(chat, None, text, true, false)
+    }
+
+    async fn edit(&self, chat: ChatId, id: MessageId, text: &str) -> Result<(), TgErrors> {
+        let m = {
+            let mut s = self.server.lock().unwrap();
+            let m = s.message(chat, id)?;
+            m.text = text.to_string();
+            m.clone()
+        };
+        self.emit(TgUpdate::MessageEdited(m));
+        Ok(())
+    }
+
+    async fn delete(&self, chat: ChatId, ids: &[MessageId]) -> Result<(), TgErrors> {
+        {
+            let mut s = self.server.lock().unwrap();
+            s.chat(chat)?;
+            if let Some(l) = s.messages.get_mut(&chat) {
+                l.retain(|m| !ids.contains(&m.id));
+            }
+        }
+        self.emit(TgUpdate::MessagesDeleted {
+            chat: None,
+            ids: ids.to_vec(),
+        });
+        Ok(())
+    }
+
+    async fn download(&self, chat: ChatId, id: MessageId, path: &Path) -> Result<(), TgErrors> {
+        let m = self.server.lock().unwrap().message(chat, id)?.clone();
+        if !m.media {
+            return Err(TgErrors::NoMedia(id));
+        }
+        fs::write(path, m.text.as_bytes())?;
+        Ok(())
+    }
+
+    async fn next_update(&self) -> Result<Option<TgUpdate>, TgErrors> {
+        match self.rx.lock().await.recv().await {
+            Some(Some(u)) => Ok(Some(u)),
+            Some(None) => Err(TgErrors::Network(io::Error::from(io::ErrorKind::ConnectionReset))),
+            None => Ok(None),
+        }
+    }
+
+    async fn reconnect(&self) -> Result<(), TgErrors> {
+        let mut s = self.server.lock().unwrap();
+        if s.failed_reconnects > 0 {
+            s.failed_reconnects -= 1;
+            return Err(TgErrors::Network(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        }
+        s.offline = false;
+        Ok(())
+    }
+
+    async fn difference(&self) -> Result<Vec<TgUpdate>, TgErrors> {
+        Ok(std::mem::take(&mut self.server.lock().unwrap().missed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn login_needs_requested_code() {
+        let fake = FakeClient::new();
+        assert!(matches!(fake.sign_in(FAKE_CODE).await, Err(TgErrors::NoLoginCode)));
+        fake.request_login_code("+79990000000").await.unwrap();
+        assert!(matches!(fake.sign_in("00000").await, Err(TgErrors::SignIn(SignInError::InvalidCode))));
+        fake.sign_in(FAKE_CODE).await.unwrap();
+        assert!(fake.is_authorized().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn incoming_message_raises_chat_and_emits_update() {
+        let fake = FakeClient::new();
+        fake.add_chat(1, "A");
+        fake.add_chat(2, "B");
+        let id = fake.receive(2, "Bob", "hello");
+
+        let dialogs = fake.dialogs().await.unwrap();
+        assert_eq!(dialogs[0].id, 2);
+        assert_eq!(dialogs[0].unread, 1);
+        match fake.next_update().await.unwrap() {
+            Some(TgUpdate::NewMessage(m)) => assert_eq!((m.id, m.chat, m.text.as_str()), (id, 2, "hello")),
+            u => panic!("unexpected {:?}", u),
+        }
+    }
+
+    #[tokio::test]
+    async fn history_is_paged_newest_first() {
+        let fake = FakeClient::new();
+        fake.add_chat(1, "A");
+        for i in 0..5 {
+            fake.send(1, &i.to_string()).await.unwrap();
+        }
+        let page: Vec<_> = fake.history(1, 0, 2).await.unwrap().into_iter().map(|m| m.text).collect();
+        assert_eq!(page, vec!["4", "3"]);
+        let older = fake.history(1, 4, 10).await.unwrap();
+        assert_eq!(older.len(), 3);
+        assert!(matches!(fake.history(9, 0, 1).await, Err(TgErrors::UnknownChat(9))));
+    }
+
+    #[tokio::test]
+    async fn edit_and_delete_emit_updates() {
+        let fake = FakeClient::new();
+        fake.add_chat(1, "A");
+        let m = fake.send(1, "typo").await.unwrap();
+        fake.edit(1, m.id, "fixed").await.unwrap();
+        fake.delete(1, &[m.id]).await.unwrap();
+
+        assert!(matches!(fake.next_update().await.unwrap(), Some(TgUpdate::MessageEdited(e)) if e.text == "fixed"));
+        assert!(matches!(fake.next_update().await.unwrap(), Some(TgUpdate::MessagesDeleted { ids, .. }) if ids == vec![m.id]));
+        assert!(fake.messages(1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn archive_and_mute_change_lists() {
+        let fake = FakeClient::new();
+        fake.add_chat(1, "A");
+        fake.add_chat(2, "B");
+        fake.archive(2).await.unwrap();
+        assert_eq!(fake.dialogs().await.unwrap().len(), 1);
+        assert_eq!(fake.folder_dialogs(ARCHIVE_FOLDER).await.unwrap()[0].id, 2);
+
+        fake.set_mute(2, i32::MAX).await.unwrap();
+        fake.unarchive(2).await.unwrap();
+        let dialogs = fake.dialogs().await.unwrap();
+        assert_eq!((dialogs[0].id, dialogs[0].muted), (2, true));
+        assert!(fake.folder_dialogs(ARCHIVE_FOLDER).await.unwrap().is_empty());
+        assert!(matches!(fake.archive(9).await, Err(TgErrors::UnknownChat(9))));
+    }
+}
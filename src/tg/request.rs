@@ -0,0 +1,136 @@
+//! Every Telegram call of `GrammersClient` goes through `call`: waits of FLOOD_WAIT and SLOWMODE_WAIT
+//! are slept with tokio timer and idempotent calls are repeated after them
+
+use std::{io, time::Duration};
+
+use futures::Future;
+use grammers_client::client::updates::InvocationError;
+use tokio::time::sleep;
+
+use super::TgErrors;
+
+/// Waits longer than it are returned as error, resolver must not hang for minutes
+pub const MAX_AUTO_WAIT: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Same result if repeated: reads, state, reconnect. Login, sending, edit and delete are not repeated
+    pub idempotent: bool,
+    pub attempts: u32,
+    pub max_wait: u32,
+}
+
+pub const READ: Policy = Policy {
+    idempotent: true,
+    attempts: 3,
+    max_wait: MAX_AUTO_WAIT,
+};
+
+pub const WRITE: Policy = Policy {
+    idempotent: false,
+    attempts: 1,
+    max_wait: 0,
+};
+
+/// Typed error by RPC name, grammers puts number of `FLOOD_WAIT_42` to `value`.
+/// Not RPC errors are I/O of connection: dropped, read or transport failure
+pub fn classify(e: InvocationError) -> TgErrors {
+    match e {
+        InvocationError::Rpc(r) => match r.name.as_str() {
+            "FLOOD_WAIT" | "FLOOD_PREMIUM_WAIT" => TgErrors::FloodWait(r.value.unwrap_or(1)),
+            "SLOWMODE_WAIT" => TgErrors::SlowMode(r.value.unwrap_or(1)),
+            "PEER_FLOOD" => TgErrors::PeerFlood,
+            _ => TgErrors::Rpc { code: r.code, name: r.name.clone() },
+        },
+        e => TgErrors::Network(io::Error::new(io::ErrorKind::ConnectionAborted, e)),
+    }
+}
+
+/// Seconds to wait before repeat, `None` when error is final
+pub fn wait_of(e: &TgErrors) -> Option<u32> {
+    match e {
+        TgErrors::FloodWait(s) | TgErrors::SlowMode(s) => Some(*s),
+        _ => None,
+    }
+}
+
+pub async fn call<T, F, Fut>(policy: Policy, mut f: F) -> Result<T, TgErrors>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, TgErrors>>,
+{
+    let mut attempt = 1;
+    loop {
+        let e = match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+        match wait_of(&e) {
+            Some(s) if policy.idempotent && attempt < policy.attempts && s <= policy.max_wait => {
+                tracing::info!(wait = s, attempt, "{}", e);
+                sleep(Duration::from_secs(s as u64)).await;
+                attempt += 1;
+            }
+            _ => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    async fn count_calls(policy: Policy, fail_with: fn() -> TgErrors, fails: u32) -> (Result<u32, TgErrors>, u32) {
+        let calls = AtomicU32::new(0);
+        let counter = &calls;
+        let r = call(policy, || async move {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            if n <= fails {
+                Err(fail_with())
+            } else {
+                Ok(n)
+            }
+        })
+        .await;
+        (r, calls.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn read_is_repeated_after_wait() {
+        let (r, calls) = count_calls(READ, || TgErrors::FloodWait(0), 2).await;
+        assert_eq!(r.unwrap(), 3);
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn attempts_are_limited() {
+        let (r, calls) = count_calls(READ, || TgErrors::SlowMode(0), 5).await;
+        assert!(matches!(r, Err(TgErrors::SlowMode(0))));
+        assert_eq!(calls, READ.attempts);
+    }
+
+    #[tokio::test]
+    async fn write_and_long_waits_are_not_repeated() {
+        let (r, calls) = count_calls(WRITE, || TgErrors::FloodWait(0), 1).await;
+        assert!(matches!(r, Err(TgErrors::FloodWait(0))));
+        assert_eq!(calls, 1);
+
+        let (r, calls) = count_calls(READ, || TgErrors::FloodWait(MAX_AUTO_WAIT + 1), 1).await;
+        assert!(matches!(r, Err(TgErrors::FloodWait(_))));
+        assert_eq!(calls, 1);
+
+        let (_, calls) = count_calls(READ, || TgErrors::PeerFlood, 1).await;
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn messages_are_readable() {
+        assert_eq!(TgErrors::FloodWait(42).to_string(), "Too many requests, wait 42 s");
+        assert_eq!(
+            TgErrors::Rpc { code: 400, name: String::from("CHAT_WRITE_FORBIDDEN") }.to_string(),
+            "Telegram refused request: CHAT_WRITE_FORBIDDEN (400)"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Keeps updates stream alive: reconnects with exponential backoff and recovers missed updates
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::mpsc, time::sleep};
+
+use super::client::{TgClient, TgUpdate};
+use super::TgErrors;
+
+/// What `App` gets instead of raw `next_update`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    Update(TgUpdate),
+    /// Stream broke or reconnect failed, next attempt after `retry_in`.
+    /// Error is given only once, attempts after it are silent.
+    Lost {
+        error: Option<String>,
+        attempt: u32,
+        retry_in: Duration,
+    },
+    /// Connected again, missed updates were sent as `Update` before it
+    Restored { missed: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            next: initial,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before next attempt, doubled every time up to `max`
+    pub fn next_delay(&mut self) -> Duration {
+        self.attempt += 1;
+        let d = self.next;
+        self.next = (self.next * 2).min(self.max);
+        d
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+        self.attempt = 0;
+    }
+}
+
+/// Reads updates in own task, channel is closed when `App` drops receiver
+pub fn supervise(client: Arc<dyn TgClient>, backoff: Backoff) -> mpsc::UnboundedReceiver<Link> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(run(client, backoff, tx));
+    rx
+}
+
+async fn run(client: Arc<dyn TgClient>, mut backoff: Backoff, tx: mpsc::UnboundedSender<Link>) {
+    loop {
+        let mut error = match client.next_update().await {
+            Ok(Some(u)) => {
+                if tx.send(Link::Update(u)).is_err() {
+                    return;
+                }
+                continue;
+            }
+            Ok(None) => None,
+            Err(e) => Some(super::report(&e)),
+        };
+        tracing::warn!(error = ?error, "updates stream lost");
+        loop {
+            let retry_in = backoff.next_delay();
+            let lost = Link::Lost {
+                error: error.take(),
+                attempt: backoff.attempt(),
+                retry_in,
+            };
+            if tx.send(lost).is_err() {
+                return;
+            }
+            sleep(retry_in).await;
+            match restore(client.as_ref()).await {
+                Ok(missed) => {
+                    tracing::info!(missed = missed.len(), attempt = backoff.attempt(), "reconnected");
+                    let n = missed.len();
+                    for u in missed {
+                        if tx.send(Link::Update(u)).is_err() {
+                            return;
+                        }
+                    }
+                    if tx.send(Link::Restored { missed: n }).is_err() {
+                        return;
+                    }
+                    backoff.reset();
+                    break;
+                }
+                Err(e) => tracing::debug!(error = %super::report(&e), "reconnect failed"),
+            }
+        }
+    }
+}
+
+async fn restore(client: &dyn TgClient) -> Result<Vec<TgUpdate>, TgErrors> {
+    client.reconnect().await?;
+    client.difference().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg::FakeClient;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut b = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays: Vec<_> = (0..5).map(|_| b.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+        assert_eq!(b.attempt(), 5);
+        b.reset();
+        assert_eq!(b.next_delay(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_recovers_missed() {
+        let fake = Arc::new(FakeClient::new());
+        fake.add_chat(1, "A");
+        let client: Arc<dyn TgClient> = fake.clone();
+        let mut links = supervise(client, Backoff::new(Duration::from_millis(1), Duration::from_millis(4)));
+
+        fake.receive(1, "Bob", "before");
+        assert!(matches!(links.recv().await, Some(Link::Update(TgUpdate::NewMessage(m))) if m.text == "before"));
+
+        fake.fail_reconnects(1);
+        fake.disconnect();
+        fake.receive(1, "Bob", "missed 1");
+        fake.receive(1, "Bob", "missed 2");
+
+        match links.recv().await {
+            Some(Link::Lost { error: Some(_), attempt: 1, .. }) => {}
+            l => panic!("unexpected {:?}", l),
+        }
+        assert!(matches!(links.recv().await, Some(Link::Lost { error: None, attempt: 2, .. })));
+        let mut texts = Vec::new();
+        for _ in 0..2 {
+            match links.recv().await {
+                Some(Link::Update(TgUpdate::NewMessage(m))) => texts.push(m.text),
+                l => panic!("unexpected {:?}", l),
+            }
+        }
+        assert_eq!(texts, vec!["missed 1", "missed 2"]);
+        assert_eq!(links.recv().await, Some(Link::Restored { missed: 2 }));
+
+        fake.receive(1, "Bob", "after");
+        assert!(matches!(links.recv().await, Some(Link::Update(TgUpdate::NewMessage(m))) if m.text == "after"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::{collections::HashMap, env};
+
+use serde::{Deserialize, Serialize};
+use tui::style::{Color, Modifier, Style};
+
+use crate::config::{AppConfig, ConfigError};
+
+pub const BUILTIN: &[&str] = &["dark", "light", "monochrome"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub selection: Style,
+    pub unread: Style,
+    pub own_message: Style,
+    pub timestamp: Style,
+    pub border: Style,
+    pub error: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+/// Style slot in config, colors by name (`red`, `lightgreen`), index (`42`) or `#rrggbb`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reversed: bool,
+}
+
+/// User theme, not set slots are taken from `base`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub base: Option<String>,
+    pub selection: Option<StyleConfig>,
+    pub unread: Option<StyleConfig>,
+    pub own_message: Option<StyleConfig>,
+    pub timestamp: Option<StyleConfig>,
+    pub border: Option<StyleConfig>,
+    pub error: Option<StyleConfig>,
+}
+
+fn parse_color(s: &str) -> Result<Color, ConfigError> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(v) = u32::from_str_radix(hex, 16) {
+                return Ok(Color::Rgb((v >> 16) as u8, (v >> 8) as u8, v as u8));
+            }
+        }
+        return Err(ConfigError::Invalid(format!("bad color {:?}", s)));
+    }
+    if let Ok(i) = s.parse::<u8>() {
+        return Ok(Color::Indexed(i));
+    }
+    Ok(match s.to_lowercase().replace(&['-', '_'][..], "").as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "darkgray" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return Err(ConfigError::Invalid(format!("bad color {:?}", s))),
+    })
+}
+
+impl StyleConfig {
+    pub fn style(&self) -> Result<Style, ConfigError> {
+        let mut st = Style::default();
+        if let Some(fg) = &self.fg {
+            st = st.fg(parse_color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            st = st.bg(parse_color(bg)?);
+        }
+        let mut m = Modifier::empty();
+        m.set(Modifier::BOLD, self.bold);
+        m.set(Modifier::ITALIC, self.italic);
+        m.set(Modifier::UNDERLINED, self.underline);
+        m.set(Modifier::REVERSED, self.reversed);
+        Ok(st.add_modifier(m))
+    }
+}
+
+fn slot(base: Style, over: &Option<StyleConfig>) -> Result<Style, ConfigError> {
+    match over {
+        Some(s) => s.style(),
+        None => Ok(base),
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            selection: Style::default().bg(Color::LightGreen),
+            unread: Style::default().add_modifier(Modifier::BOLD),
+            own_message: Style::default().fg(Color::LightCyan),
+            timestamp: Style::default().fg(Color::DarkGray),
+            border: Style::default(),
+            error: Style::default().fg(Color::Red),
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            selection: Style::default().bg(Color::Blue).fg(Color::White),
+            unread: Style::default().fg(Color::Black).add_modifier(Modifier::BOLD),
+            own_message: Style::default().fg(Color::Blue),
+            timestamp: Style::default().fg(Color::Gray),
+            border: Style::default().fg(Color::DarkGray),
+            error: Style::default().fg(Color::Red),
+        }
+    }
+
+    /// Only modifiers, used for `NO_COLOR`
+    pub fn monochrome() -> Self {
+        Theme {
+            selection: Style::default().add_modifier(Modifier::REVERSED),
+            unread: Style::default().add_modifier(Modifier::BOLD),
+            own_message: Style::default().add_modifier(Modifier::ITALIC),
+            timestamp: Style::default().add_modifier(Modifier::DIM),
+            border: Style::default(),
+            error: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "monochrome" => Some(Theme::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// https://no-color.org: any not empty value disables colors
+    pub fn no_color() -> bool {
+        env::var_os("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false)
+    }
+
+    pub fn resolve(name: &str, themes: &HashMap<String, ThemeConfig>) -> Result<Self, ConfigError> {
+        Self::resolve_depth(name, themes, 0)
+    }
+
+    fn resolve_depth(name: &str, themes: &HashMap<String, ThemeConfig>, depth: usize) -> Result<Self, ConfigError> {
+        if depth > themes.len() {
+            return Err(ConfigError::Invalid(format!("theme {:?} has cyclic base", name)));
+        }
+        let tc = match themes.get(name) {
+            Some(t) => t,
+            None => {
+                return Theme::builtin(name).ok_or_else(|| {
+                    ConfigError::Invalid(format!("unknown theme {:?}, builtin are {:?}", name, BUILTIN))
+                })
+            }
+        };
+        let base = match &tc.base {
+            Some(b) => Self::resolve_depth(b, themes, depth + 1)?,
+            None => Theme::default(),
+        };
+        Ok(Theme {
+            selection: slot(base.selection, &tc.selection)?,
+            unread: slot(base.unread, &tc.unread)?,
+            own_message: slot(base.own_message, &tc.own_message)?,
+            timestamp: slot(base.timestamp, &tc.timestamp)?,
+            border: slot(base.border, &tc.border)?,
+            error: slot(base.error, &tc.error)?,
+        })
+    }
+
+    pub fn from_config(config: &AppConfig) -> Result<Self, ConfigError> {
+        let theme = Self::resolve(&config.theme, &config.themes)?;
+        if Self::no_color() {
+            Ok(Theme::monochrome())
+        } else {
+            Ok(theme)
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Derive macros for teleconsole ECS, see `ecs::state` and `ecs::SystemStruct`
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, Lit, LitInt, LitStr,
+    Meta, NestedMeta, Path,
+};
+
+/// Object-safe state for `ecs::System`: `Eq` and `Hash` by variant.
+/// Only enums without fields.
+#[proc_macro_derive(SystemState)]
+pub fn derive_system_state(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    system_state(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Struct as system, fields are locals of system.
+///
+/// ```ignore
+/// #[derive(System)]
+/// #[system(state = "LoginState", init = "PreLogin", end = "EndLogin")]
+/// #[drawer(PreLogin = "draw_phone")]
+/// #[resolver(PreLogin = "resolve_phone")]
+/// #[subsystem(CodeInput = 2)]
+/// struct Login { phone: String }
+/// ```
+///
+/// Drawer is `async fn(&self, &ArgumentDrawer<'_>)`,
+/// resolver is `async fn(&mut self, ArgumentResolver) -> State`.
+#[proc_macro_derive(System, attributes(system, drawer, resolver, subsystem))]
+pub fn derive_system(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    system(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn system_state(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "SystemState can`t be generic"));
+    }
+    let data = match &input.data {
+        Data::Enum(e) => e,
+        _ => return Err(Error::new_spanned(name, "SystemState can be derived only for enums")),
+    };
+    for v in data.variants.iter() {
+        if !matches!(v.fields, Fields::Unit) {
+            return Err(Error::new_spanned(v, "SystemState variants must not have fields"));
+        }
+    }
+
+    Ok(quote! {
+        impl ::core::cmp::PartialEq for #name {
+            fn eq(&self, other: &Self) -> bool {
+                ::core::mem::discriminant(self) == ::core::mem::discriminant(other)
+            }
+        }
+
+        impl ::core::cmp::Eq for #name {}
+
+        impl ::core::hash::Hash for #name {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                ::core::hash::Hash::hash(&::core::mem::discriminant(self), state)
+            }
+        }
+    })
+}
+
+struct SystemAttrs {
+    state: Option<Path>,
+    init: Option<Ident>,
+    end: Option<Ident>,
+    drawers: Vec<(Ident, Ident)>,
+    resolvers: Vec<(Ident, Ident)>,
+    subsystems: Vec<(Ident, LitInt)>,
+}
+
+fn pairs(attr: &Attribute) -> syn::Result<Vec<(Ident, Lit)>> {
+    let list = match attr.parse_meta()? {
+        Meta::List(l) => l,
+        m => return Err(Error::new_spanned(m, "expected list: #[attr(Name = value, ...)]")),
+    };
+    let mut out = Vec::new();
+    for nested in list.nested.iter() {
+        match nested {
+            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                let ident = nv
+                    .path
+                    .get_ident()
+                    .cloned()
+                    .ok_or_else(|| Error::new_spanned(&nv.path, "expected identifier"))?;
+                out.push((ident, nv.lit.clone()));
+            }
+            n => return Err(Error::new_spanned(n, "expected Name = value")),
+        }
+    }
+    Ok(out)
+}
+
+fn lit_str(lit: &Lit) -> syn::Result<&LitStr> {
+    match lit {
+        Lit::Str(s) => Ok(s),
+        l => Err(Error::new_spanned(l, "expected string")),
+    }
+}
+
+fn parse_attrs(input: &DeriveInput) -> syn::Result<SystemAttrs> {
+    let mut attrs = SystemAttrs {
+        state: None,
+        init: None,
+        end: None,
+        drawers: Vec::new(),
+        resolvers: Vec::new(),
+        subsystems: Vec::new(),
+    };
+    for attr in input.attrs.iter() {
+        if attr.path.is_ident("system") {
+            for (k, v) in pairs(attr)? {
+                let v = lit_str(&v)?;
+                match k.to_string().as_str() {
+                    "state" => attrs.state = Some(v.parse()?),
+                    "init" => attrs.init = Some(v.parse()?),
+                    "end" => attrs.end = Some(v.parse()?),
+                    _ => return Err(Error::new_spanned(k, "expected state, init or end")),
+                }
+            }
+        } else if attr.path.is_ident("drawer") {
+            for (k, v) in pairs(attr)? {
+                attrs.drawers.push((k, lit_str(&v)?.parse()?));
+            }
+        } else if attr.path.is_ident("resolver") {
+            for (k, v) in pairs(attr)? {
+                attrs.resolvers.push((k, lit_str(&v)?.parse()?));
+            }
+        } else if attr.path.is_ident("subsystem") {
+            for (k, v) in pairs(attr)? {
+                match v {
+                    Lit::Int(i) => attrs.subsystems.push((k, i)),
+                    l => return Err(Error::new_spanned(l, "expected system id")),
+                }
+            }
+        }
+    }
+    Ok(attrs)
+}
+
+fn system(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "System can`t be generic"));
+    }
+    if !matches!(input.data, Data::Struct(_)) {
+        return Err(Error::new_spanned(name, "System can be derived only for structs"));
+    }
+    let attrs = parse_attrs(&input)?;
+    let missing = |what: &str| Error::new_spanned(name, format!("missing #[system({} = \"...\")]", what));
+    let state = attrs.state.as_ref().ok_or_else(|| missing("state"))?;
+    let init = attrs.init.as_ref().ok_or_else(|| missing("init"))?;
+    let end = attrs.end.as_ref().ok_or_else(|| missing("end"))?;
+
+    let mut wrappers = Vec::new();
+    let mut register = Vec::new();
+    for (i, (st, method)) in attrs.drawers.iter().enumerate() {
+        let f = format_ident!("__system_drawer_{}", i);
+        wrappers.push(quote! {
+            #[doc(hidden)]
+            fn #f<'a>(
+                arg: crate::ecs::ArgumentDrawer<'a>,
+            ) -> ::std::pin::Pin<::std::boxed::Box<
+                dyn ::std::future::Future<Output = ::std::result::Result<(), crate::ecs::EcsErrors>>
+                    + ::std::marker::Send
+                    + 'a,
+            >> {
+                ::std::boxed::Box::pin(async move {
+                    let this = crate::ecs::system_struct::<#name>(&arg.local).await?;
+                    let this = this.lock().await;
+                    this.#method(&arg).await;
+                    ::std::result::Result::Ok(())
+                })
+            }
+        });
+        register.push(quote! {
+            system.add_drawer(::std::boxed::Box::new(#state::#st), #name::#f as crate::ecs::DrawerPtr);
+        });
+    }
+    for (i, (st, method)) in attrs.resolvers.iter().enumerate() {
+        let f = format_ident!("__system_resolver_{}", i);
+        wrappers.push(quote! {
+            #[doc(hidden)]
+            fn #f<'a>(
+                _system: &'a mut crate::ecs::System<crate::ecs::SystemState>,
+                arg: crate::ecs::ArgumentResolver,
+            ) -> ::std::pin::Pin<::std::boxed::Box<
+                dyn ::std::future::Future<
+                    Output = ::std::result::Result<crate::ecs::SystemState, crate::ecs::EcsErrors>,
+                > + ::std::marker::Send
+                    + 'a,
+            >> {
+                ::std::boxed::Box::pin(async move {
+                    let this = crate::ecs::system_struct::<#name>(&arg.local).await?;
+                    let mut this = this.lock().await;
+                    let state: crate::ecs::SystemState = ::std::boxed::Box::new(this.#method(arg).await);
+                    ::std::result::Result::Ok(state)
+                })
+            }
+        });
+        register.push(quote! {
+            system.set_resolver(
+                ::std::boxed::Box::new(#state::#st),
+                #name::#f as crate::ecs::ResolverPtr<crate::ecs::SystemState>,
+            );
+        });
+    }
+    for (st, id) in attrs.subsystems.iter() {
+        register.push(quote! {
+            system.set_subsystem(::std::boxed::Box::new(#state::#st), #id);
+        });
+    }
+
+    Ok(quote! {
+        impl #name {
+            #( #wrappers )*
+        }
+
+        impl crate::ecs::SystemStruct for #name {
+            type State = #state;
+
+            fn init_state() -> Self::State {
+                #state::#init
+            }
+
+            fn end_state() -> Self::State {
+                #state::#end
+            }
+
+            fn register(system: &mut crate::ecs::System<crate::ecs::SystemState>) {
+                use crate::ecs::ExecSystemLocals;
+                #( #register )*
+            }
+        }
+    })
+}